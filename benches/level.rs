@@ -0,0 +1,128 @@
+use bevy::{ecs::schedule::SystemStage, prelude::*};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use pacbomber::game_plugin::{
+    enemy_direction, enemy_logic, move_entities, Enemy, Level, Location, Movement, Player,
+    Position, Speed, ENEMY_SPEED_EASY, PLAYER_SPEED,
+};
+
+const SIZE: usize = 200;
+
+/// A level of `SIZE` × `SIZE` tiles in the format of the maps in `LEVELS`:
+/// walls around it, pillars on every other tile, and coins and enemies in between
+fn level_data() -> String {
+    let mut data = String::new();
+    for z in 0..SIZE {
+        for x in 0..SIZE {
+            let border = x == 0 || z == 0 || x == SIZE - 1 || z == SIZE - 1;
+            let symbol = match (x, z) {
+                _ if border => '#',
+                (1, 1) => 'o',
+                (2, 1) => 'O',
+                (x, z) if x == SIZE - 2 && z == SIZE - 2 => 'e',
+                (x, z) if x % 2 == 0 && z % 2 == 0 => '#',
+                (x, z) if (x + z) % 7 == 0 => 'x',
+                (x, z) if (x * z) % 5 == 0 => '*',
+                _ => ' ',
+            };
+            data.push(symbol);
+        }
+        data.push('\n');
+    }
+    data
+}
+
+/// Every tile that can be walked on
+fn free_positions(level: &Level) -> Vec<Position> {
+    level
+        .rows()
+        .flat_map(|row| row.iter())
+        .filter(|block| !block.kind.is_wall())
+        .map(|block| block.level_position)
+        .collect()
+}
+
+fn level(c: &mut Criterion) {
+    let data = level_data();
+    c.bench_function("new_data", |b| {
+        b.iter(|| Level::from_data(black_box(&data), 2))
+    });
+
+    let mut level = Level::from_data(&data, 2);
+    let free = free_positions(&level);
+    c.bench_function("wall_positions", |b| {
+        // right above the bottom wall, which runs around the whole level
+        b.iter(|| level.wall_positions(black_box(Position::new(1, SIZE - 2))))
+    });
+    c.bench_function("free_directions", |b| {
+        b.iter(|| {
+            for position in free.iter() {
+                black_box(level.free_directions(*position));
+            }
+        })
+    });
+
+    let bomb = Entity::from_raw(0);
+    level.bomb_size = SIZE;
+    level.place_bomb(bomb, Position::new(SIZE / 2 - 1, SIZE / 2 - 1));
+    c.bench_function("bomb_explode_positions", |b| {
+        b.iter(|| level.bomb_explode_positions(black_box(bomb)))
+    });
+
+    let players = [Vec2::new(-50.0, -50.0), Vec2::new(50.0, 50.0)];
+    c.bench_function("enemy_direction", |b| {
+        b.iter(|| {
+            for position in free.iter() {
+                let v = level.translate_from_position(*position);
+                let v = Vec2::new(v.x, v.z);
+                black_box(enemy_direction(&level, *position, v, &players));
+            }
+        })
+    });
+}
+
+/// Every free tile of the level has an enemy that hunts the players
+fn world_with_enemies() -> (World, SystemStage) {
+    let mut world = World::new();
+    let level = Level::from_data(&level_data(), 2);
+    for (index, position) in level.player_positions.clone() {
+        world
+            .spawn()
+            .insert(Player(index))
+            .insert(Location(position))
+            .insert(Movement::default())
+            .insert(Speed(PLAYER_SPEED))
+            .insert(Transform::from_translation(
+                level.translate_from_position(position),
+            ));
+    }
+    for position in free_positions(&level) {
+        world
+            .spawn()
+            .insert(Enemy)
+            .insert(Location(position))
+            .insert(Movement::default())
+            .insert(Speed(ENEMY_SPEED_EASY))
+            .insert(Transform::from_translation(
+                level.translate_from_position(position),
+            ));
+    }
+    world.insert_resource(level);
+    let stage = SystemStage::single_threaded()
+        .with_system(enemy_logic)
+        .with_system(move_entities.after(enemy_logic));
+    (world, stage)
+}
+
+fn ecs(c: &mut Criterion) {
+    c.bench_function("enemy_frame", |b| {
+        b.iter_batched_ref(
+            world_with_enemies,
+            |(world, stage)| stage.run(world),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, level, ecs);
+criterion_main!(benches);
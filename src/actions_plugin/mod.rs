@@ -0,0 +1,183 @@
+use crate::settings::{Action, Settings, PLAYERS};
+use bevy::{input::InputSystem, prelude::*, ui::UiSystem};
+use std::cmp::Ordering;
+
+/// Turns keyboard and gamepad input into an `Input<Action>` resource, so that
+/// the game doesn't need to care which device the player uses
+pub struct ActionsPlugin;
+
+/// The actions of every single player. The first gamepad belongs to the first
+/// player, the second one to the second player
+#[derive(Default)]
+pub struct PlayerActions(pub [Input<Action>; PLAYERS]);
+
+/// Label of the system that fills the actions, anything replacing them has to run after it
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UpdateActions;
+
+/// Buttons that can also be selected with the keyboard or a gamepad
+#[derive(Component)]
+pub struct Navigable {
+    /// The color of the button while it isn't focused
+    pub color: Color,
+}
+
+const FOCUSED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Input<Action>>()
+            .init_resource::<PlayerActions>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_actions.label(UpdateActions).after(InputSystem),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                navigate_buttons
+                    .after(UiSystem::Focus)
+                    .after(update_actions),
+            );
+    }
+}
+
+fn gamepad_buttons(action: Action) -> &'static [GamepadButtonType] {
+    match action {
+        Action::MoveUp => &[GamepadButtonType::DPadUp],
+        Action::MoveDown => &[GamepadButtonType::DPadDown],
+        Action::MoveLeft => &[GamepadButtonType::DPadLeft],
+        Action::MoveRight => &[GamepadButtonType::DPadRight],
+        Action::PlaceBomb => &[GamepadButtonType::South],
+        Action::Pause => &[GamepadButtonType::Start],
+        Action::Confirm => &[GamepadButtonType::South],
+        Action::Back => &[GamepadButtonType::Select, GamepadButtonType::East],
+        Action::Fullscreen | Action::TopDown => &[],
+    }
+}
+
+/// The stick axis and the direction along it that triggers an action
+fn gamepad_stick(action: Action) -> Option<(GamepadAxisType, f32)> {
+    match action {
+        Action::MoveUp => Some((GamepadAxisType::LeftStickY, 1.0)),
+        Action::MoveDown => Some((GamepadAxisType::LeftStickY, -1.0)),
+        Action::MoveLeft => Some((GamepadAxisType::LeftStickX, -1.0)),
+        Action::MoveRight => Some((GamepadAxisType::LeftStickX, 1.0)),
+        _ => None,
+    }
+}
+
+/// `actions` is the union of all players and drives everything outside of the level
+fn update_actions(
+    mut actions: ResMut<Input<Action>>,
+    mut player_actions: ResMut<PlayerActions>,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    settings: Res<Settings>,
+) {
+    let mut gamepads: Vec<_> = gamepads.iter().copied().collect();
+    gamepads.sort_by_key(|gamepad| gamepad.id);
+    let gamepad_active = |gamepad: Gamepad, action: Action| {
+        let button_active = gamepad_buttons(action).iter().any(|button_type| {
+            let button = GamepadButton::new(gamepad, *button_type);
+            buttons.pressed(button) || buttons.just_pressed(button)
+        });
+        let stick_active = gamepad_stick(action).is_some_and(|(axis_type, sign)| {
+            let value = axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0);
+            value * sign > settings.stick_deadzone
+        });
+        button_active || stick_active
+    };
+
+    actions.clear();
+    for input in player_actions.0.iter_mut() {
+        input.clear();
+    }
+    for action in Action::ALL {
+        let mut any_active = false;
+        for (player, input) in player_actions.0.iter_mut().enumerate() {
+            // keys that were pressed and released within one frame still count
+            let bindings = settings.bindings(player);
+            let keyboard_active =
+                bindings.pressed(action, &keyboard) || bindings.just_pressed(action, &keyboard);
+            let active = keyboard_active
+                || gamepads
+                    .get(player)
+                    .is_some_and(|gamepad| gamepad_active(*gamepad, action));
+            set_active(input, action, active);
+            any_active |= active;
+        }
+        // additional gamepads can still be used in the menus
+        any_active |= gamepads
+            .iter()
+            .skip(PLAYERS)
+            .any(|gamepad| gamepad_active(*gamepad, action));
+        set_active(&mut actions, action, any_active);
+    }
+}
+
+fn set_active(input: &mut Input<Action>, action: Action, active: bool) {
+    if active {
+        input.press(action);
+    } else {
+        input.release(action);
+    }
+}
+
+/// Moves the focus between `Navigable` buttons in reading order and clicks the
+/// focused one on `Confirm`. A new screen starts with its first button focused
+fn navigate_buttons(
+    actions: Res<Input<Action>>,
+    mut focus: Local<Option<usize>>,
+    mut buttons: Query<(&GlobalTransform, &Navigable, &mut UiColor, &mut Interaction)>,
+    added: Query<(), Added<Navigable>>,
+) {
+    let new_screen = !added.is_empty();
+    if new_screen {
+        *focus = Some(0);
+    }
+    let previous = actions.any_just_pressed([Action::MoveUp, Action::MoveLeft]);
+    let next = actions.any_just_pressed([Action::MoveDown, Action::MoveRight]);
+    let confirm = actions.just_pressed(Action::Confirm);
+    if !new_screen && !previous && !next && !confirm {
+        return;
+    }
+
+    let mut sorted: Vec<_> = buttons.iter_mut().collect();
+    if sorted.is_empty() {
+        return;
+    }
+    // ui coordinates grow upwards, so the top row has the largest y
+    sorted.sort_by(|(a, ..), (b, ..)| {
+        let (a, b) = (a.translation(), b.translation());
+        b.y.partial_cmp(&a.y)
+            .unwrap_or(Ordering::Equal)
+            .then(a.x.partial_cmp(&b.x).unwrap_or(Ordering::Equal))
+    });
+
+    let count = sorted.len();
+    let index = match *focus {
+        Some(n) if new_screen => n.min(count - 1),
+        Some(n) if previous => (n + count - 1) % count,
+        Some(n) if next => (n + 1) % count,
+        Some(n) => n.min(count - 1),
+        None => 0,
+    };
+    // a press in the frame the screen shows up was meant for the one before
+    let click = confirm && !new_screen;
+    *focus = Some(index);
+
+    for (current, (_, navigable, mut color, mut interaction)) in sorted.into_iter().enumerate() {
+        if current != index {
+            *color = navigable.color.into();
+            continue;
+        }
+        *color = FOCUSED_BUTTON.into();
+        if click {
+            *interaction = Interaction::Clicked;
+        }
+    }
+}
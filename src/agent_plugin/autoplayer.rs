@@ -0,0 +1,713 @@
+use bevy::{ecs::entity::Entity, math::Vec2, prelude::default, utils::HashMap};
+use std::collections::VecDeque;
+
+use crate::game_plugin::{
+    burn_fuse, enemy_direction, explosion_ticks, move_step, sizes, GameMode, Level, LevelView,
+    Observation, Position, BOMB_SECONDS, ENEMY_SPEED_EASY, PLAYER_SPEED,
+};
+
+use super::{Agent, AgentAction, Direction};
+
+/// Plans are simulated this many ticks ahead at most
+const HORIZON: u32 = 240;
+/// A plan walks to a tile at most this many steps away
+const SEARCH_DEPTH: usize = 8;
+/// How long a plan has to stay safe after its last step, enough for an enemy
+/// to walk a few tiles
+const SAFE_TICKS: u32 = 60;
+/// Enemies further away than this aren't worth a bomb
+const BOMB_DISTANCE: u32 = 7;
+
+/// A scripted player for the campaign levels. It walks towards the enemies
+/// until they follow it, drops a bomb where they will walk into it and retreats
+/// out of its reach, then collects coins on the way to the exit. Every move is
+/// checked by simulating the next seconds of the level, with the rules of the
+/// game taken from the game itself
+pub struct Autoplayer {
+    players: Vec<usize>,
+    grid: Grid,
+    /// The level as far as it is known, one tick ahead of the last observation
+    state: Sim,
+    mode: GameMode,
+    /// Bombs are placed on a press, so the key has to be released in between
+    bombed: Vec<usize>,
+}
+
+impl Autoplayer {
+    pub fn new(players: Vec<usize>) -> Self {
+        Self {
+            players,
+            grid: Grid::new(&[]),
+            state: Sim::default(),
+            mode: GameMode::Campaign,
+            bombed: Vec::new(),
+        }
+    }
+
+    /// Takes over what was seen, but keeps the movements that can't be seen
+    fn observe(&mut self, observation: &Observation) {
+        let state = &mut self.state;
+        for view in observation.players.iter() {
+            let player = match state.players.iter_mut().find(|p| p.index == view.index) {
+                Some(n) => n,
+                None => {
+                    state.players.push(SimPlayer {
+                        index: view.index,
+                        mover: Mover::at(view.position),
+                        alive: true,
+                        exited: false,
+                        plan: VecDeque::new(),
+                        bomb: None,
+                    });
+                    state.players.last_mut().unwrap()
+                }
+            };
+            player.alive = view.alive;
+            player.exited = view.exited;
+            if player.mover.tile != view.position {
+                player.mover = Mover::at(view.position);
+            }
+        }
+
+        // an enemy that isn't where it was expected just arrived at its tile
+        let mut known = std::mem::take(&mut state.enemies);
+        for position in observation.enemies.iter() {
+            let mover = match known.iter().position(|enemy| enemy.tile == *position) {
+                Some(index) => known.swap_remove(index),
+                None => Mover::at(*position),
+            };
+            state.enemies.push(mover);
+        }
+
+        state.coins = observation.coins.clone();
+        state.bombs = observation
+            .bombs
+            .iter()
+            .map(|bomb| (bomb.position, bomb.fuse, false))
+            .collect();
+        state.placed.clear();
+        // bombs next to each other burn some tiles twice
+        let range = self.grid.level.bomb_size;
+        let mut known = std::mem::take(&mut state.explosions);
+        for position in observation.explosions.iter() {
+            let ticks = match known.iter().position(|(tile, ..)| tile == position) {
+                Some(index) => known.swap_remove(index).1,
+                None => explosion_ticks(range - 1, range),
+            };
+            state.explosions.push((*position, ticks, false));
+        }
+        state.fresh.clear();
+        state.exit_open = observation.exit.is_some();
+    }
+
+    fn bombs_left(&self, observation: &Observation, player: usize) -> usize {
+        let index = match self.mode {
+            GameMode::Versus => player,
+            _ => 0,
+        };
+        observation.scores.get(index).map_or(0, |score| score.bombs)
+    }
+
+    /// Picks the best plan for a player standing on a tile. A plan may drop a
+    /// bomb right away or after its first step, the rest of it is the retreat
+    fn plan(
+        &self,
+        observation: &Observation,
+        player: usize,
+    ) -> (VecDeque<Direction>, Option<usize>) {
+        let from = match self.state.player(player) {
+            Some(n) => n.mover.tile,
+            None => return default(),
+        };
+        let goals = Goals::new(&self.grid, &self.state);
+        let can_bomb = |tile: Position| {
+            self.bombs_left(observation, player) > 0
+                && self.state.bombs.iter().all(|(bomb, ..)| *bomb != tile)
+                && goals.enemy(tile) <= BOMB_DISTANCE
+        };
+        let mut candidates = Vec::new();
+        for path in self.grid.paths(from, SEARCH_DEPTH) {
+            if can_bomb(from) {
+                candidates.push((path.clone(), Some(0)));
+            }
+            candidates.push((path, None));
+        }
+        for (direction, next) in self.grid.neighbours(from) {
+            if !can_bomb(next) {
+                continue;
+            }
+            for path in self.grid.paths(next, SEARCH_DEPTH - 1) {
+                let mut longer = vec![direction];
+                longer.extend(path);
+                candidates.push((longer, Some(1)));
+            }
+        }
+
+        let mut best: Option<(f32, Vec<Direction>, Option<usize>)> = None;
+        for (path, bomb) in candidates {
+            let score = match self.evaluate(player, &path, bomb, &goals) {
+                Some(n) => n,
+                None => continue,
+            };
+            if best.as_ref().is_none_or(|(best, ..)| score > *best) {
+                best = Some((score, path, bomb));
+            }
+        }
+        best.map(|(_, path, bomb)| (path.into(), bomb))
+            .unwrap_or_default()
+    }
+
+    /// Simulates the plan, bombs are only worth it if they kill
+    fn evaluate(
+        &self,
+        player: usize,
+        path: &[Direction],
+        bomb: Option<usize>,
+        goals: &Goals,
+    ) -> Option<f32> {
+        let mut sim = self.state.clone();
+        sim.cautious = true;
+        let mover = sim.player_mut(player)?;
+        mover.plan = path.iter().copied().collect();
+        mover.bomb = bomb;
+        let walk = (path.len() as u32 + 1) * move_ticks(PLAYER_SPEED);
+        let range = self.grid.level.bomb_size;
+        let burn = explosion_ticks(range - 1, range);
+        let fuses = sim
+            .bombs
+            .iter()
+            .map(|(_, fuse, _)| fuse_ticks(*fuse) + burn)
+            .chain(bomb.map(|steps| {
+                steps as u32 * move_ticks(PLAYER_SPEED) + fuse_ticks(BOMB_SECONDS) + burn
+            }))
+            .max()
+            .unwrap_or(0);
+        let horizon = (walk + SAFE_TICKS).max(fuses + 1).min(HORIZON);
+
+        let mut exited = false;
+        for tick in 0..horizon {
+            sim.step(&self.grid);
+            let lost = self
+                .players
+                .iter()
+                .filter_map(|index| sim.player(*index))
+                .any(|player| !player.alive);
+            if lost {
+                // being caught later is better than being caught now
+                return Some(-1_000_000.0 + tick as f32);
+            }
+            let player = sim.player(player)?;
+            if player.exited {
+                exited = true;
+                break;
+            }
+        }
+        if bomb.is_some() && sim.planned_kills == 0 {
+            return None;
+        }
+
+        let end = sim.player(player)?.mover.tile;
+        let mut score = 1000.0 * sim.kills as f32 + 20.0 * sim.coins_taken as f32;
+        score -= path.len() as f32;
+        if exited {
+            score += 100_000.0;
+        } else if sim.exit_open {
+            score -= 30.0 * goals.exit(end) as f32;
+        } else if !self.state.enemies.is_empty() {
+            // close enough to be followed, but not any closer
+            score -= 10.0 * goals.enemy(end).max(2) as f32;
+        } else {
+            score -= 5.0 * goals.coin(end) as f32;
+        }
+        Some(score)
+    }
+}
+
+impl Agent for Autoplayer {
+    fn start(&mut self, level: &LevelView) {
+        self.grid = Grid::new(&level.rows);
+        self.state = Sim {
+            exit: self.grid.level.ending_position,
+            ..default()
+        };
+        self.mode = level.mode;
+        self.bombed.clear();
+    }
+
+    fn act(&mut self, observation: &Observation) -> Vec<AgentAction> {
+        self.observe(observation);
+        for player in self.players.clone() {
+            let idle = self
+                .state
+                .player(player)
+                .is_some_and(|p| p.alive && !p.exited && p.mover.moving.is_none());
+            if !idle {
+                continue;
+            }
+            let (plan, bomb) = self.plan(observation, player);
+            if let Some(state) = self.state.player_mut(player) {
+                state.plan = plan;
+                state.bomb = bomb;
+            }
+        }
+        // the simulation starts the planned moves, like the game will
+        self.state.step(&self.grid);
+        let mut bombs = std::mem::take(&mut self.state.pressed);
+        bombs.retain(|player| !self.bombed.contains(player));
+        let actions = self
+            .players
+            .iter()
+            .filter_map(|index| self.state.player(*index))
+            .map(|player| AgentAction {
+                player: player.index,
+                direction: player.mover.moving.map(|(direction, _)| direction),
+                bomb: bombs.contains(&player.index),
+            })
+            .collect();
+        self.bombed = bombs;
+        actions
+    }
+}
+
+/// The level without anything that moves, which answers where players,
+/// enemies and bombs can go
+struct Grid {
+    level: Level,
+    /// The tiles a bomb on a tile reaches and how long they burn
+    blasts: HashMap<Position, Vec<(Position, u32)>>,
+}
+
+fn opposite(direction: Direction) -> Direction {
+    match direction {
+        Direction::Up => Direction::Down,
+        Direction::Down => Direction::Up,
+        Direction::Left => Direction::Right,
+        Direction::Right => Direction::Left,
+    }
+}
+
+impl Grid {
+    fn new(rows: &[String]) -> Self {
+        let mut level = Level::from_layout(rows);
+        let tiles: Vec<_> = level
+            .rows()
+            .flatten()
+            .filter(|block| !block.kind.is_wall())
+            .map(|block| block.level_position)
+            .collect();
+        // a bomb that is only there to ask the level how far it reaches
+        let bomb = Entity::from_raw(0);
+        let mut blasts = HashMap::default();
+        for tile in tiles {
+            level.place_bomb(bomb, tile);
+            let blast = level
+                .bomb_explode_positions(bomb)
+                .into_iter()
+                .map(|(position, strength, max)| (position, explosion_ticks(strength, max)))
+                .collect();
+            level.remove_bomb(bomb);
+            blasts.insert(tile, blast);
+        }
+        Self { level, blasts }
+    }
+
+    /// The tiles next to a tile that can be walked on, and how to get there
+    fn neighbours(&self, position: Position) -> Vec<(Direction, Position)> {
+        self.level
+            .free_directions(position)
+            .into_iter()
+            .filter_map(|board| {
+                let mut next = position;
+                next.apply_direction(&board);
+                Some((Direction::from_board(board)?, next))
+            })
+            .collect()
+    }
+
+    fn step(&self, position: Position, direction: Direction) -> Option<Position> {
+        self.neighbours(position)
+            .into_iter()
+            .find(|(candidate, _)| *candidate == direction)
+            .map(|(_, next)| next)
+    }
+
+    fn blast(&self, position: Position) -> &[(Position, u32)] {
+        self.blasts.get(&position).map_or(&[], Vec::as_slice)
+    }
+
+    /// The shortest path to every tile up to `depth` steps away, including the
+    /// empty path to stay and the ways to step off the tile and back on
+    fn paths(&self, from: Position, depth: usize) -> Vec<Vec<Direction>> {
+        let mut paths: HashMap<Position, Vec<Direction>> = HashMap::default();
+        paths.insert(from, Vec::new());
+        let mut queue = VecDeque::from([from]);
+        while let Some(tile) = queue.pop_front() {
+            let path = paths[&tile].clone();
+            if path.len() >= depth {
+                continue;
+            }
+            for (direction, next) in self.neighbours(tile) {
+                if paths.contains_key(&next) {
+                    continue;
+                }
+                let mut longer = path.clone();
+                longer.push(direction);
+                paths.insert(next, longer);
+                queue.push_back(next);
+            }
+        }
+        let mut paths: Vec<_> = paths.into_values().collect();
+        for (direction, _) in self.neighbours(from) {
+            paths.push(vec![direction, opposite(direction)]);
+        }
+        // the same level always gets the same plans
+        paths.sort();
+        paths
+    }
+
+    /// Steps from every tile to the closest of `sources`
+    fn distances(&self, sources: impl Iterator<Item = Position>) -> HashMap<Position, u32> {
+        let mut distances = HashMap::default();
+        let mut queue = VecDeque::new();
+        for source in sources {
+            distances.insert(source, 0);
+            queue.push_back(source);
+        }
+        while let Some(tile) = queue.pop_front() {
+            let distance = distances[&tile];
+            for (_, next) in self.neighbours(tile) {
+                if !distances.contains_key(&next) {
+                    distances.insert(next, distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        distances
+    }
+}
+
+/// How far every tile is from what the player wants to reach
+struct Goals {
+    enemies: HashMap<Position, u32>,
+    exit: HashMap<Position, u32>,
+    coins: HashMap<Position, u32>,
+}
+
+/// Tiles that can't be reached count as far away
+const UNREACHABLE: u32 = 1000;
+
+impl Goals {
+    fn new(grid: &Grid, state: &Sim) -> Self {
+        Self {
+            enemies: grid.distances(state.enemies.iter().map(|enemy| enemy.tile)),
+            exit: grid.distances(state.exit.into_iter()),
+            coins: grid.distances(state.coins.iter().copied()),
+        }
+    }
+
+    fn enemy(&self, tile: Position) -> u32 {
+        self.enemies.get(&tile).copied().unwrap_or(UNREACHABLE)
+    }
+
+    fn exit(&self, tile: Position) -> u32 {
+        self.exit.get(&tile).copied().unwrap_or(UNREACHABLE)
+    }
+
+    fn coin(&self, tile: Position) -> u32 {
+        self.coins.get(&tile).copied().unwrap_or(UNREACHABLE)
+    }
+}
+
+/// The frames a move takes at the speed
+fn move_ticks(speed: f32) -> u32 {
+    let mut remaining = sizes::field.x;
+    let mut ticks = 0;
+    while remaining > 0.0 {
+        remaining -= move_step(speed);
+        ticks += 1;
+    }
+    ticks
+}
+
+/// The frames until a fuse burned down
+fn fuse_ticks(fuse: f32) -> u32 {
+    let mut fuse = fuse;
+    let mut ticks = 0;
+    while fuse > 0.0 {
+        fuse = burn_fuse(fuse);
+        ticks += 1;
+    }
+    ticks
+}
+
+/// A player or an enemy, and the move it is in
+#[derive(Clone)]
+struct Mover {
+    tile: Position,
+    /// The direction and the distance that is left, in the units of the game
+    moving: Option<(Direction, f32)>,
+}
+
+impl Mover {
+    fn at(tile: Position) -> Self {
+        Self { tile, moving: None }
+    }
+
+    /// Where it is between the tiles, like the translation of its entity
+    fn translation(&self, level: &Level) -> Vec2 {
+        let tile = level.translate_from_position(self.tile);
+        let tile = Vec2::new(tile.x, tile.z);
+        match self.moving {
+            Some((direction, remaining)) => {
+                tile + direction.board() * Vec2::splat(sizes::field.x - remaining)
+            }
+            None => tile,
+        }
+    }
+
+    /// The tile it is on and the one it moves to
+    fn tiles(&self, grid: &Grid) -> [Position; 2] {
+        let target = self
+            .moving
+            .and_then(|(direction, _)| grid.step(self.tile, direction));
+        [self.tile, target.unwrap_or(self.tile)]
+    }
+
+    /// Returns true once it arrived at the next tile
+    fn advance(&mut self, grid: &Grid, speed: f32) -> bool {
+        let (direction, remaining) = match &mut self.moving {
+            Some(n) => n,
+            None => return false,
+        };
+        *remaining -= move_step(speed);
+        if *remaining > 0.0 {
+            return false;
+        }
+        self.tile = grid.step(self.tile, *direction).unwrap_or(self.tile);
+        self.moving = None;
+        true
+    }
+}
+
+#[derive(Clone)]
+struct SimPlayer {
+    index: usize,
+    mover: Mover,
+    alive: bool,
+    exited: bool,
+    /// The moves still to make
+    plan: VecDeque<Direction>,
+    /// The moves to make before placing a bomb
+    bomb: Option<usize>,
+}
+
+/// The moving parts of a level, advanced tick by tick in the order the
+/// systems of the game run in
+#[derive(Clone, Default)]
+struct Sim {
+    players: Vec<SimPlayer>,
+    enemies: Vec<Mover>,
+    /// Position, the fuse and whether it was only planned
+    bombs: Vec<(Position, f32, bool)>,
+    /// Bombs placed in this tick, they start burning in the next one
+    placed: Vec<Position>,
+    /// The players that pressed the bomb key in the last tick
+    pressed: Vec<usize>,
+    /// Position, the ticks it still burns and whether its bomb was only planned
+    explosions: Vec<(Position, u32, bool)>,
+    /// Explosions of this tick, they only hurt from the next one on
+    fresh: Vec<(Position, u32, bool)>,
+    coins: Vec<Position>,
+    exit: Option<Position>,
+    exit_open: bool,
+    kills: usize,
+    /// The kills of planned bombs
+    planned_kills: usize,
+    coins_taken: usize,
+    /// Counts coming close to an enemy as being caught, which leaves room for
+    /// moves that aren't exactly what the game does
+    cautious: bool,
+}
+
+impl Sim {
+    fn player(&self, index: usize) -> Option<&SimPlayer> {
+        self.players.iter().find(|player| player.index == index)
+    }
+
+    fn player_mut(&mut self, index: usize) -> Option<&mut SimPlayer> {
+        self.players.iter_mut().find(|player| player.index == index)
+    }
+
+    fn step(&mut self, grid: &Grid) {
+        // keyboard input
+        self.pressed.clear();
+        for player in self.players.iter_mut() {
+            if !player.alive || player.exited {
+                continue;
+            }
+            if player.mover.moving.is_some() {
+                continue;
+            }
+            if player.bomb == Some(0) {
+                player.bomb = None;
+                let tile = player.mover.tile;
+                if !self.bombs.iter().any(|(bomb, ..)| *bomb == tile) {
+                    self.placed.push(tile);
+                }
+                self.pressed.push(player.index);
+            }
+            if let Some(direction) = player.plan.pop_front() {
+                player.mover.moving = Some((direction, sizes::field.x));
+                if let Some(steps) = &mut player.bomb {
+                    *steps -= 1;
+                }
+            }
+        }
+
+        // enemies hunt the closest player
+        let targets: Vec<_> = self
+            .players
+            .iter()
+            .filter(|player| player.alive && !player.exited)
+            .map(|player| player.mover.translation(&grid.level))
+            .collect();
+        for enemy in self.enemies.iter_mut() {
+            if enemy.moving.is_some() {
+                continue;
+            }
+            let at = enemy.translation(&grid.level);
+            let direction = enemy_direction(&grid.level, enemy.tile, at, &targets)
+                .and_then(Direction::from_board);
+            enemy.moving = direction.map(|direction| (direction, sizes::field.x));
+        }
+
+        // movement
+        let arrived_enemies: Vec<_> = self
+            .enemies
+            .iter_mut()
+            .map(|enemy| enemy.advance(grid, ENEMY_SPEED_EASY))
+            .collect();
+        let mut arrived_players = Vec::new();
+        for player in self.players.iter_mut() {
+            if player.alive && !player.exited && player.mover.advance(grid, PLAYER_SPEED) {
+                arrived_players.push(player.index);
+            }
+        }
+
+        // enemies catch players, players collect coins and leave
+        for (enemy, arrived) in self.enemies.iter().zip(arrived_enemies) {
+            for player in self.players.iter_mut() {
+                if arrived && player.alive && !player.exited && player.mover.tile == enemy.tile {
+                    player.alive = false;
+                }
+            }
+        }
+        for player in self.players.iter_mut() {
+            if !player.alive || player.exited || !arrived_players.contains(&player.index) {
+                continue;
+            }
+            let tile = player.mover.tile;
+            if self.enemies.iter().any(|enemy| enemy.tile == tile) {
+                player.alive = false;
+            } else if self.exit_open && self.exit == Some(tile) {
+                player.exited = true;
+            } else if let Some(coin) = self.coins.iter().position(|coin| *coin == tile) {
+                self.coins.swap_remove(coin);
+                self.coins_taken += 1;
+            }
+        }
+        if self.cautious {
+            for player in self.players.iter_mut() {
+                if !player.alive || player.exited {
+                    continue;
+                }
+                let tiles = player.mover.tiles(grid);
+                let close = self
+                    .enemies
+                    .iter()
+                    .any(|enemy| enemy.tiles(grid).iter().any(|tile| tiles.contains(tile)));
+                if close {
+                    player.alive = false;
+                }
+            }
+        }
+
+        // fuses and explosions
+        let mut exploded = Vec::new();
+        self.bombs.retain_mut(|(tile, fuse, planned)| {
+            *fuse = burn_fuse(*fuse);
+            if *fuse <= 0.0 {
+                exploded.push((*tile, *planned));
+            }
+            *fuse > 0.0
+        });
+        for (tile, planned) in exploded {
+            let blast = grid.blast(tile).iter();
+            self.fresh
+                .extend(blast.map(|(tile, ticks)| (*tile, *ticks, planned)));
+        }
+        for (tile, ticks, planned) in self.explosions.iter_mut() {
+            *ticks = ticks.saturating_sub(1);
+            for player in self.players.iter_mut() {
+                if player.alive && !player.exited && player.mover.tile == *tile {
+                    player.alive = false;
+                }
+            }
+            let before = self.enemies.len();
+            self.enemies.retain(|enemy| enemy.tile != *tile);
+            let kills = before - self.enemies.len();
+            self.kills += kills;
+            if *planned {
+                self.planned_kills += kills;
+            }
+        }
+        self.explosions.retain(|(_, ticks, _)| *ticks > 0);
+        if self.enemies.is_empty() && self.exit.is_some() && !self.exit_open {
+            self.exit_open = true;
+            self.coins.clear();
+        }
+
+        // what was spawned in this tick shows up in the next one
+        self.explosions.append(&mut self.fresh);
+        for tile in self.placed.drain(..) {
+            self.bombs.push((tile, BOMB_SECONDS, true));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agent_plugin::{AgentControl, Outcome},
+        game_plugin::{BOMB_RANGE, LEVELS},
+        headless_plugin::harness::Harness,
+    };
+
+    /// Ten minutes of play
+    const MAX_TICKS: usize = 36_000;
+
+    fn play(level: usize) -> Option<Outcome> {
+        let mut harness = Harness::campaign(level);
+        harness
+            .app
+            .insert_resource(AgentControl::new(Autoplayer::new(vec![0]), vec![0]));
+        harness.run_until(MAX_TICKS, |world| world.contains_resource::<Outcome>());
+        harness.app.world.get_resource::<Outcome>().copied()
+    }
+
+    #[test]
+    fn test_every_level_can_be_completed() {
+        for level in 0..LEVELS.len() {
+            assert_eq!(play(level), Some(Outcome::Completed), "level {}", level + 1);
+        }
+    }
+
+    #[test]
+    fn test_move_ticks() {
+        assert_eq!(move_ticks(PLAYER_SPEED), 16);
+        assert_eq!(fuse_ticks(BOMB_SECONDS), 91);
+        assert_eq!(explosion_ticks(0, BOMB_RANGE), 30);
+        assert_eq!(explosion_ticks(2, BOMB_RANGE), 50);
+    }
+}
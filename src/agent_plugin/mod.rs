@@ -0,0 +1,227 @@
+mod autoplayer;
+mod stdio;
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions_plugin::{PlayerActions, UpdateActions},
+    game_plugin::{BoardDirection, LevelView, Observation, Observer},
+    settings::{Action, PLAYERS},
+    GameState,
+};
+
+pub use autoplayer::Autoplayer;
+pub use stdio::StdioAgent;
+
+/// Lets programs play the game. An agent sees the running level like a
+/// spectator does and picks the actions of its players every tick
+pub struct AgentPlugin;
+
+/// An automated player, for one or more players of the game
+pub trait Agent: Send + Sync {
+    /// Called whenever a level starts, before its first tick
+    fn start(&mut self, _level: &LevelView) {}
+    /// The actions for the next tick. Players without an action press nothing
+    fn act(&mut self, observation: &Observation) -> Vec<AgentAction>;
+    /// Called once a headless run is over
+    fn end(&mut self, _observation: &Observation, _outcome: Outcome) {}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn action(&self) -> Action {
+        match self {
+            Direction::Up => Action::MoveUp,
+            Direction::Down => Action::MoveDown,
+            Direction::Left => Action::MoveLeft,
+            Direction::Right => Action::MoveRight,
+        }
+    }
+
+    fn board(&self) -> BoardDirection {
+        match self {
+            Direction::Up => BoardDirection::new(0, -1),
+            Direction::Down => BoardDirection::new(0, 1),
+            Direction::Left => BoardDirection::new(-1, 0),
+            Direction::Right => BoardDirection::new(1, 0),
+        }
+    }
+
+    fn from_board(direction: BoardDirection) -> Option<Self> {
+        Direction::ALL
+            .into_iter()
+            .find(|candidate| candidate.board() == direction)
+    }
+}
+
+/// What one player does in a tick. Like a key, a direction or the bomb stays
+/// pressed for as long as it is repeated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentAction {
+    pub player: usize,
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub bomb: bool,
+}
+
+/// How a headless run ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Every player either left through the exit or died, and at least one left
+    Completed,
+    Lost,
+    /// A versus round was decided
+    RoundOver,
+}
+
+/// The agent and the players it controls. Insert it to hand players over to
+/// an agent, remove it to give them back
+pub struct AgentControl {
+    agent: Box<dyn Agent>,
+    players: Vec<usize>,
+    inputs: [Input<Action>; PLAYERS],
+}
+
+impl AgentControl {
+    pub fn new(agent: impl Agent + 'static, players: Vec<usize>) -> Self {
+        Self {
+            agent: Box::new(agent),
+            players,
+            inputs: default(),
+        }
+    }
+
+    /// Presses what the actions ask for and releases everything else
+    fn apply(&mut self, actions: &[AgentAction]) {
+        for player in self.players.iter().copied() {
+            let action = actions.iter().find(|action| action.player == player);
+            let input = &mut self.inputs[player];
+            input.clear();
+            let direction = action.and_then(|action| action.direction);
+            for candidate in Direction::ALL {
+                set_pressed(input, candidate.action(), direction == Some(candidate));
+            }
+            set_pressed(
+                input,
+                Action::PlaceBomb,
+                action.is_some_and(|action| action.bomb),
+            );
+        }
+    }
+}
+
+fn set_pressed(input: &mut Input<Action>, action: Action, pressed: bool) {
+    if pressed {
+        input.press(action);
+    } else {
+        input.release(action);
+    }
+}
+
+impl Plugin for AgentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            agent_system
+                .with_run_criteria(run_agent)
+                .after(UpdateActions),
+        )
+        .add_system_to_stage(CoreStage::Last, end_system.with_run_criteria(run_ended));
+    }
+}
+
+/// State driven system sets only work in the update stage
+fn run_agent(control: Option<Res<AgentControl>>, app_state: Res<State<GameState>>) -> ShouldRun {
+    if control.is_some() && app_state.current() == &GameState::Running {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Replaces the actions of the controlled players with the ones of the agent
+fn agent_system(
+    mut control: ResMut<AgentControl>,
+    mut player_actions: ResMut<PlayerActions>,
+    observer: Observer,
+) {
+    if observer.level_changed() {
+        control.agent.start(&observer.describe_level());
+    }
+    let actions = control.agent.act(&observer.observe());
+    control.apply(&actions);
+    for player in control.players.iter().copied() {
+        player_actions.0[player] = control.inputs[player].clone();
+    }
+}
+
+fn run_ended(outcome: Option<Res<Outcome>>) -> ShouldRun {
+    if outcome.is_some_and(|outcome| outcome.is_added()) {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn end_system(control: Option<ResMut<AgentControl>>, outcome: Res<Outcome>, observer: Observer) {
+    if let Some(mut control) = control {
+        control.agent.end(&observer.observe(), *outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Still;
+
+    impl Agent for Still {
+        fn act(&mut self, _: &Observation) -> Vec<AgentAction> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_actions_are_held_like_keys() {
+        let mut control = AgentControl::new(Still, vec![1]);
+        let left = AgentAction {
+            player: 1,
+            direction: Some(Direction::Left),
+            bomb: true,
+        };
+        control.apply(std::slice::from_ref(&left));
+        let input = &control.inputs[1];
+        assert!(input.just_pressed(Action::MoveLeft) && input.just_pressed(Action::PlaceBomb));
+
+        control.apply(&[left]);
+        let input = &control.inputs[1];
+        assert!(input.pressed(Action::MoveLeft) && !input.just_pressed(Action::MoveLeft));
+
+        // actions for players the agent doesn't control are ignored
+        control.apply(&[AgentAction {
+            player: 0,
+            direction: Some(Direction::Up),
+            bomb: false,
+        }]);
+        assert!(control.inputs[0].get_pressed().next().is_none());
+        assert!(control.inputs[1].just_released(Action::MoveLeft));
+    }
+}
@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+
+use crate::game_plugin::{LevelView, Observation};
+
+use super::{Agent, AgentAction, Outcome};
+
+/// An agent in another process, talking JSON lines over stdin and stdout. It
+/// gets a message for every level and every tick, and answers every tick with
+/// the actions to take. See the README for the format
+#[derive(Default)]
+pub struct StdioAgent {
+    tick: u64,
+    /// Set once stdin is closed, nothing is read after that
+    closed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message<'a> {
+    Level(&'a LevelView),
+    Step {
+        tick: u64,
+        #[serde(flatten)]
+        state: &'a Observation,
+    },
+    End {
+        outcome: Outcome,
+        #[serde(flatten)]
+        state: &'a Observation,
+    },
+}
+
+impl Message<'_> {
+    fn line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("messages are valid JSON");
+        line.push('\n');
+        line
+    }
+}
+
+impl StdioAgent {
+    fn send(&self, message: &Message) {
+        let mut stdout = io::stdout().lock();
+        if let Err(e) = stdout
+            .write_all(message.line().as_bytes())
+            .and_then(|_| stdout.flush())
+        {
+            warn!("Could not talk to the agent: {e}");
+        }
+    }
+
+    fn receive(&mut self) -> Vec<AgentAction> {
+        if self.closed {
+            return Vec::new();
+        }
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                self.closed = true;
+                Vec::new()
+            }
+            Ok(_) => parse_actions(&line),
+        }
+    }
+}
+
+/// Anything that doesn't parse counts as doing nothing
+fn parse_actions(line: &str) -> Vec<AgentAction> {
+    serde_json::from_str(line).unwrap_or_else(|e| {
+        warn!("Ignoring the actions {:?}: {e}", line.trim());
+        Vec::new()
+    })
+}
+
+impl Agent for StdioAgent {
+    fn start(&mut self, level: &LevelView) {
+        self.send(&Message::Level(level));
+    }
+
+    fn act(&mut self, observation: &Observation) -> Vec<AgentAction> {
+        self.send(&Message::Step {
+            tick: self.tick,
+            state: observation,
+        });
+        self.tick += 1;
+        self.receive()
+    }
+
+    fn end(&mut self, observation: &Observation, outcome: Outcome) {
+        self.send(&Message::End {
+            outcome,
+            state: observation,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Direction;
+    use super::*;
+
+    #[test]
+    fn test_parse_actions() {
+        let actions =
+            parse_actions("[{\"player\":0,\"direction\":\"left\",\"bomb\":true},{\"player\":1}]\n");
+        assert_eq!(
+            actions,
+            vec![
+                AgentAction {
+                    player: 0,
+                    direction: Some(Direction::Left),
+                    bomb: true,
+                },
+                AgentAction {
+                    player: 1,
+                    direction: None,
+                    bomb: false,
+                },
+            ]
+        );
+        assert!(parse_actions("left\n").is_empty());
+    }
+}
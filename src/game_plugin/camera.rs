@@ -0,0 +1,160 @@
+use bevy::{
+    prelude::*,
+    render::camera::{Projection, ScalingMode},
+};
+
+use crate::{settings::Settings, MaterialHandles};
+
+use super::level::Level;
+use super::statics::{
+    sizes, CAMERA_MARGIN, CAMERA_SPEED, CAMERA_VIEW, MAX_CAMERA_DISTANCE, MAX_TOP_DOWN_VIEW,
+    MIN_CAMERA_DISTANCE, TOP_DOWN_HEIGHT,
+};
+use super::types::*;
+
+/// The extent of the level on the ground
+fn level_extent(level: &Level) -> Vec2 {
+    Vec2::new(
+        level.size.x as f32 * sizes::field.x,
+        level.size.z as f32 * sizes::field.z,
+    )
+}
+
+/// How much of the ground the camera sees around the point it looks at, from
+/// the distance. The camera looks down at the level, which shortens its depth
+fn visible_extent(distance: f32, fov: f32, aspect: f32) -> Vec2 {
+    let height = 2.0 * distance * (fov / 2.0).tan();
+    let tilt = CAMERA_VIEW.y / CAMERA_VIEW.length();
+    Vec2::new(height * aspect, height / tilt)
+}
+
+/// The distance along `CAMERA_VIEW` from which the whole level is in view
+pub fn framing_distance(extent: Vec2, fov: f32, aspect: f32) -> f32 {
+    let unit = visible_extent(1.0, fov, aspect);
+    let margin = Vec2::splat(2.0 * CAMERA_MARGIN);
+    let distance = (extent + margin) / unit;
+    distance.max_element().max(MIN_CAMERA_DISTANCE)
+}
+
+/// The ground the top-down camera shows, at least the whole level up to a
+/// limit
+fn top_down_view(extent: Vec2) -> Vec2 {
+    (extent + Vec2::splat(2.0 * CAMERA_MARGIN)).min(Vec2::splat(MAX_TOP_DOWN_VIEW))
+}
+
+/// Moves the camera to where it frames the level, or follows the players on
+/// levels that are too large for that. In the top-down view, the camera looks
+/// straight down without perspective
+#[allow(clippy::type_complexity)]
+pub fn frame_level(
+    time: Res<Time>,
+    level: Res<Level>,
+    settings: Res<Settings>,
+    players: Query<&Transform, (With<Player>, With<Movement>, Without<Camera3d>)>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<Camera3d>>,
+) {
+    let (mut transform, mut projection) = match camera.get_single_mut() {
+        Ok(n) => n,
+        Err(_) => return,
+    };
+    let extent = level_extent(&level);
+    let (visible, eye, up) = if settings.top_down {
+        let view = top_down_view(extent);
+        let framed = matches!(
+            projection.as_ref(),
+            Projection::Orthographic(OrthographicProjection {
+                scaling_mode: ScalingMode::Auto { min_width, min_height },
+                ..
+            }) if *min_width == view.x && *min_height == view.y
+        );
+        if !framed {
+            *projection = Projection::Orthographic(OrthographicProjection {
+                scaling_mode: ScalingMode::Auto {
+                    min_width: view.x,
+                    min_height: view.y,
+                },
+                ..default()
+            });
+        }
+        // rows go down the screen like in the level data
+        (view, Vec3::Y * TOP_DOWN_HEIGHT, -Vec3::Z)
+    } else {
+        let (fov, aspect) = match projection.as_ref() {
+            Projection::Perspective(n) => (n.fov, n.aspect_ratio),
+            Projection::Orthographic(_) => {
+                *projection = Projection::Perspective(default());
+                return;
+            }
+        };
+        let distance = framing_distance(extent, fov, aspect).min(MAX_CAMERA_DISTANCE);
+        let visible = visible_extent(distance, fov, aspect);
+        (visible, CAMERA_VIEW.normalize() * distance, Vec3::Y)
+    };
+    let mut target = Vec3::ZERO;
+    let count = players.iter().count();
+    if count > 0 {
+        let center = players.iter().map(|n| &n.translation).sum::<Vec3>() / count as f32;
+        // levels that fit leave no room, and larger ones don't show more than
+        // needed of what is around them
+        let room = ((extent - visible) / 2.0 + CAMERA_MARGIN).max(Vec2::ZERO);
+        target = Vec3::new(
+            center.x.clamp(-room.x, room.x),
+            0.0,
+            center.z.clamp(-room.y, room.y),
+        );
+    }
+    let goal = Transform::from_translation(target + eye).looking_at(target, up);
+    let step = 1.0 - (-CAMERA_SPEED * time.delta_seconds()).exp();
+    transform.translation = transform.translation.lerp(goal.translation, step);
+    transform.rotation = transform.rotation.slerp(goal.rotation, step);
+}
+
+/// The top-down view has flat colours without light and shadows
+pub fn flat_colours(
+    settings: Res<Settings>,
+    handles: Res<MaterialHandles>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for handle in handles.all() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.unlit = settings.top_down;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_plugin::LEVELS;
+    use std::f32::consts::FRAC_PI_4;
+
+    #[test]
+    fn test_framing_distance() {
+        let aspect = 900.0 / 660.0;
+        let distance = |level: &Level| framing_distance(level_extent(level), FRAC_PI_4, aspect);
+        // the campaign fits about the fixed view it was made for
+        for level in 0..LEVELS.len() {
+            let distance = distance(&Level::new(level, 1));
+            assert!(distance <= CAMERA_VIEW.length(), "level {level}");
+        }
+        let small = Level::from_data("#o e#\n", 1);
+        assert_eq!(distance(&small), MIN_CAMERA_DISTANCE);
+        let wide = format!("o{}e\n", " ".repeat(200));
+        assert!(distance(&Level::from_data(&wide, 1)) > MAX_CAMERA_DISTANCE);
+    }
+
+    #[test]
+    fn test_top_down_view() {
+        let level = Level::new(0, 1);
+        let view = top_down_view(level_extent(&level));
+        assert_eq!(
+            view,
+            level_extent(&level) + Vec2::splat(2.0 * CAMERA_MARGIN)
+        );
+        let view = top_down_view(Vec2::new(100.0, 1.0));
+        assert_eq!(view.x, MAX_TOP_DOWN_VIEW);
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use std::collections::BTreeMap;
+
+use super::particles::ParticleBurst;
+use super::types::Ending;
+
+/// Something the simulation asks for that can't be undone by a rollback
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Sound(Handle<AudioSource>),
+    Burst(ParticleBurst),
+    /// The entity died and shrinks away, true if it is despawned afterwards
+    Implode(Entity, bool),
+    /// A player sinks into the exit
+    EnterExit(Entity),
+    /// The entity is removed once its animation is done
+    Despawn(Entity),
+    /// The ending is due
+    Ending(Ending),
+}
+
+/// The effects of the simulated ticks. Offline they are played right away, an
+/// online match holds back those of predicted ticks until the inputs of the
+/// tick are confirmed, and drops them if the tick is simulated again
+#[derive(Default)]
+pub struct Effects {
+    /// The predicted tick that is being simulated
+    holding: Option<u64>,
+    held: BTreeMap<u64, Vec<Effect>>,
+    ready: Vec<Effect>,
+}
+
+impl Effects {
+    pub fn push(&mut self, effect: Effect) {
+        match self.holding {
+            Some(tick) => self.held.entry(tick).or_default().push(effect),
+            None => self.ready.push(effect),
+        }
+    }
+
+    /// Called before `tick` is simulated. What earlier runs of it and the ticks
+    /// after it asked for is dropped, they happen again if the guess was right
+    pub fn start_tick(&mut self, tick: u64, predicted: bool) {
+        self.held.split_off(&tick);
+        self.holding = predicted.then_some(tick);
+    }
+
+    pub fn finish_tick(&mut self) {
+        self.holding = None;
+    }
+
+    /// Lets out the effects of the ticks before `confirmed`
+    pub fn confirm(&mut self, confirmed: u64) {
+        let later = self.held.split_off(&confirmed);
+        for (_, effects) in std::mem::replace(&mut self.held, later) {
+            self.ready.extend(effects);
+        }
+    }
+
+    pub fn take_ready(&mut self) -> Vec<Effect> {
+        std::mem::take(&mut self.ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effects_of_predicted_ticks_wait_for_confirmation() {
+        let effect = |n| Effect::EnterExit(Entity::from_raw(n));
+        let mut effects = Effects::default();
+        effects.start_tick(0, false);
+        effects.push(effect(0));
+        effects.finish_tick();
+        for tick in 1..4 {
+            effects.start_tick(tick, true);
+            effects.push(effect(tick as u32));
+            effects.finish_tick();
+        }
+        assert_eq!(effects.take_ready(), vec![effect(0)]);
+
+        // tick 2 was guessed wrong, its second run asks for something else
+        effects.start_tick(2, true);
+        effects.push(effect(20));
+        effects.finish_tick();
+        effects.confirm(3);
+        assert_eq!(effects.take_ready(), vec![effect(1), effect(20)]);
+        effects.confirm(4);
+        assert!(effects.take_ready().is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::PrimitiveTopology,
+    },
+    utils::HashSet,
+};
+use std::collections::BTreeMap;
+
+use crate::{settings::Settings, MaterialHandles, MeshHandles};
+
+use super::level::Level;
+use super::statics::{sizes, CHUNK_TILES};
+use super::types::*;
+
+/// Copies of the meshes, each moved by its offset, as one mesh
+pub fn merge_meshes<'a>(parts: impl IntoIterator<Item = (&'a Mesh, Vec3)>) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for (mesh, offset) in parts {
+        let start = positions.len() as u32;
+        if let Some(VertexAttributeValues::Float32x3(values)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        {
+            positions.extend(values.iter().map(|p| (Vec3::from(*p) + offset).to_array()));
+        }
+        if let Some(VertexAttributeValues::Float32x3(values)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        {
+            normals.extend_from_slice(values);
+        }
+        if let Some(VertexAttributeValues::Float32x2(values)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        {
+            uvs.extend_from_slice(values);
+        }
+        match mesh.indices() {
+            Some(Indices::U16(n)) => indices.extend(n.iter().map(|i| start + *i as u32)),
+            Some(Indices::U32(n)) => indices.extend(n.iter().map(|i| start + i)),
+            None => indices.extend(start..positions.len() as u32),
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Three bars crossing each other, the shape of an explosion when shapes tell
+/// things apart
+pub fn explosion_cue_mesh() -> Mesh {
+    let (long, thin) = (sizes::bomb_size, sizes::bomb_size / 3.0);
+    let bars = [
+        Mesh::from(shape::Box::new(long, thin, thin)),
+        Mesh::from(shape::Box::new(thin, long, thin)),
+        Mesh::from(shape::Box::new(thin, thin, long)),
+    ];
+    merge_meshes(bars.iter().map(|bar| (bar, Vec3::ZERO)))
+}
+
+/// The chunk of tiles the position is in
+fn chunk_of(position: Position) -> (usize, usize) {
+    (position.z / CHUNK_TILES, position.x / CHUNK_TILES)
+}
+
+fn wall_mesh(meshes: &Assets<Mesh>, handles: &MeshHandles, walls: &[Block]) -> Mesh {
+    merge_meshes(walls.iter().filter_map(|block| {
+        let handle = match block.kind {
+            BlockType::WallSmallV => &handles.wall_v,
+            BlockType::WallSmallH => &handles.wall_h,
+            _ => &handles.wall,
+        };
+        meshes.get(handle).map(|mesh| (mesh, block.position))
+    }))
+}
+
+/// Spawns the floor and the walls of the level merged into a few meshes for
+/// every chunk of tiles, instead of entities for every tile. The exit has its
+/// own floor, see `setup_exit_floor`
+pub fn setup_level_geometry(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    handles: &MeshHandles,
+    materials: &MaterialHandles,
+    level: &Level,
+) -> Vec<Entity> {
+    let mut floors: BTreeMap<_, Vec<Vec3>> = BTreeMap::new();
+    let mut walls: BTreeMap<_, Vec<Block>> = BTreeMap::new();
+    for block in level.rows().flat_map(|row| row.iter()) {
+        if block.kind == BlockType::Exit {
+            continue;
+        }
+        let p = block.position;
+        floors
+            .entry(chunk_of(block.level_position))
+            .or_default()
+            .push(Vec3::new(p.x, sizes::space.y - 0.01, p.z));
+        if block.kind.is_wall() {
+            walls
+                .entry(chunk_of(block.level_position))
+                .or_default()
+                .push(*block);
+        }
+    }
+
+    let mut entities = Vec::new();
+    for tiles in floors.values() {
+        let layers = [
+            (&handles.floor_fg, &materials.floor_fg, 0.0),
+            (&handles.floor_bg, &materials.floor_bg, 0.01),
+            (&handles.floor_cube, &materials.ground, -sizes::field.y),
+        ];
+        for (mesh, material, height) in layers {
+            let tile = match meshes.get(mesh) {
+                Some(n) => n,
+                None => continue,
+            };
+            let merged = merge_meshes(
+                tiles
+                    .iter()
+                    .map(|p| (tile, Vec3::new(p.x, p.y + height, p.z))),
+            );
+            let id = commands
+                .spawn_bundle(PbrBundle {
+                    mesh: meshes.add(merged),
+                    material: material.clone(),
+                    ..default()
+                })
+                .insert(Floor)
+                .id();
+            entities.push(id);
+        }
+    }
+    for walls in walls.into_values() {
+        let merged = wall_mesh(meshes, handles, &walls);
+        // nothing is see-through until a player moved
+        let see_through = commands
+            .spawn_bundle(PbrBundle {
+                mesh: meshes.add(merged.clone()),
+                material: materials.wall_hidden.clone(),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(Wall)
+            .id();
+        let id = commands
+            .spawn_bundle(PbrBundle {
+                mesh: meshes.add(merged),
+                material: materials.wall_normal.clone(),
+                ..default()
+            })
+            .insert(Wall)
+            .insert(WallChunk {
+                walls,
+                hidden: Vec::new(),
+                see_through,
+            })
+            .push_children(&[see_through])
+            .id();
+        entities.push(id);
+    }
+    entities
+}
+
+/// Replaces the mesh with the merged walls, or hides it if there are none
+fn set_walls(
+    meshes: &mut Assets<Mesh>,
+    handles: &MeshHandles,
+    mesh: &Handle<Mesh>,
+    visibility: &mut Visibility,
+    walls: &[Block],
+) {
+    visibility.is_visible = !walls.is_empty();
+    if walls.is_empty() {
+        return;
+    }
+    let merged = wall_mesh(meshes, handles, walls);
+    if let Some(mesh) = meshes.get_mut(mesh) {
+        *mesh = merged;
+    }
+}
+
+/// Makes the walls in front of every player see-through. Only the chunks
+/// where that changed are merged again. Nothing is in front of the players
+/// when they are seen from above
+#[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments)]
+pub fn wall_visibility(
+    level: Res<Level>,
+    settings: Res<Settings>,
+    moved_query: Query<(), (With<Player>, Changed<Location>)>,
+    player_query: Query<&Location, With<Player>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    handles: Res<MeshHandles>,
+    mut chunks: Query<(&mut WallChunk, &Handle<Mesh>, &mut Visibility)>,
+    mut see_through: Query<(&Handle<Mesh>, &mut Visibility), (With<Wall>, Without<WallChunk>)>,
+) {
+    if moved_query.is_empty() && !settings.is_changed() {
+        return;
+    }
+    // hide the walls in front of every player
+    let walls_below: HashSet<_> = player_query
+        .iter()
+        .filter(|_| !settings.top_down)
+        .flat_map(|location| level.wall_positions(location.0))
+        .collect();
+    for (mut chunk, mesh, mut visibility) in chunks.iter_mut() {
+        let hidden: Vec<_> = chunk
+            .walls
+            .iter()
+            .map(|block| block.level_position)
+            .filter(|position| walls_below.contains(position))
+            .collect();
+        if hidden == chunk.hidden {
+            continue;
+        }
+        let (hidden_walls, walls): (Vec<Block>, Vec<Block>) = chunk
+            .walls
+            .iter()
+            .partition(|block| walls_below.contains(&block.level_position));
+        set_walls(&mut meshes, &handles, mesh, &mut visibility, &walls);
+        if let Ok((mesh, mut visibility)) = see_through.get_mut(chunk.see_through) {
+            set_walls(&mut meshes, &handles, mesh, &mut visibility, &hidden_walls);
+        }
+        chunk.hidden = hidden;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_meshes() {
+        let cube = Mesh::from(shape::Cube { size: 1.0 });
+        let offset = Vec3::new(2.0, 0.0, 0.0);
+        let merged = merge_meshes([(&cube, Vec3::ZERO), (&cube, offset)]);
+        let vertices = cube.count_vertices();
+        assert_eq!(merged.count_vertices(), vertices * 2);
+        let indices: Vec<_> = merged.indices().unwrap().iter().collect();
+        assert_eq!(indices.len(), cube.indices().unwrap().len() * 2);
+        assert!(indices[indices.len() / 2..].iter().all(|i| *i >= vertices));
+        match merged.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => {
+                assert_eq!(positions[vertices][0], positions[0][0] + 2.0)
+            }
+            _ => panic!("no positions"),
+        }
+    }
+}
@@ -1,30 +1,96 @@
 use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
-use super::statics::{sizes, LEVELS};
+use super::occupancy::{Occupancy, Occupant};
+use super::scoring::LevelStats;
+use super::statics::{sizes, ARENAS, BOMB_RANGE, DEFAULT_THEME, LEVELS};
 use super::types::*;
 
-#[derive(Debug)]
+/// The dynamic state of a level, so that a run can be stored and resumed later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelState {
+    /// Index and position of every player that is still alive
+    pub players: Vec<(usize, Position)>,
+    /// The players that already left through the exit
+    #[serde(default)]
+    pub exited: Vec<usize>,
+    pub enemies: Vec<Position>,
+    pub coins: Vec<Position>,
+    /// Range, position and remaining fuse of every placed bomb
+    pub bombs: Vec<(usize, Position, f32)>,
+    pub ending_visible: bool,
+    pub stats: LevelStats,
+}
+
+#[derive(Debug, Clone)]
 pub struct Level {
     pub size: Position,
     pub offsets: (f32, f32),
     pub rows: Vec<Vec<Block>>,
-    pub player_position: Position,
-    pub ending_position: Position,
-    pub enemy_positions: HashMap<Entity, Position>,
-    pub coin_positions: HashMap<Entity, Position>,
-    pub bombs: HashMap<Entity, (usize, Position)>,
+    /// The players that are still alive, by their index
+    pub player_positions: HashMap<usize, Position>,
+    /// The players that already left through the exit
+    pub exited: Vec<usize>,
+    /// Arenas don't have an exit
+    pub ending_position: Option<Position>,
+    /// Enemies, coins and bombs only change through the methods below, which
+    /// keep them in line with `occupancy`
+    enemy_positions: HashMap<Entity, Position>,
+    coin_positions: HashMap<Entity, Position>,
+    bombs: HashMap<Entity, (usize, Position)>,
+    occupancy: Occupancy,
     pub bomb_size: usize,
     pub ending_visible: bool,
     pub done_loading: bool,
     pub finished: bool,
+    pub stats: LevelStats,
+    /// The ticks until each ending is due. They are counted by the simulation
+    /// and not by the animations, so that every instance of an online match
+    /// gets them on the same tick
+    pub endings: Vec<(u32, Ending)>,
+    /// Set when the level should be spawned from a stored state instead of its data
+    pub restored: Option<LevelState>,
+    /// The name of the theme the level is shown in
+    pub theme: &'static str,
 }
 
 impl Level {
-    pub fn new(level: usize) -> Self {
-        let data = LEVELS[level];
-        Level::new_data(data)
+    /// Spawns of players beyond `players` stay empty
+    pub fn new(level: usize, players: usize) -> Self {
+        let mut new = Level::from_data(LEVELS[level].map, players);
+        new.theme = LEVELS[level].theme;
+        new
+    }
+
+    /// A level in the format of the maps in `LEVELS`
+    pub fn from_data(data: &str, players: usize) -> Self {
+        let mut level = Level::playable(data);
+        level.player_positions.retain(|index, _| *index < players);
+        level
+    }
+
+    pub fn arena(arena: usize) -> Self {
+        let mut level = Level::playable(ARENAS[arena].map);
+        level.theme = ARENAS[arena].theme;
+        level
+    }
+
+    /// A level from the rows of `layout`, without players or enemies
+    pub fn from_layout(rows: &[String]) -> Self {
+        Level::new_data(&rows.join("\n"))
+    }
+
+    /// Levels that can be played need at least the first player
+    fn playable(data: &str) -> Self {
+        let level = Level::new_data(data);
+        assert!(
+            level.player_positions.contains_key(&0),
+            "Expect a player position in the level!"
+        );
+        level
     }
+
     fn new_data(data: &str) -> Self {
         let mut rows: Vec<Vec<_>> = Vec::new();
 
@@ -36,7 +102,7 @@ impl Level {
         let z_size = lines.len();
         let mut x_size = 0;
 
-        let mut player_position: Option<Position> = None;
+        let mut player_positions = HashMap::new();
         let mut ending_position: Option<Position> = None;
 
         for (x_index, line) in lines.iter().enumerate() {
@@ -52,8 +118,8 @@ impl Level {
 
                 let level_position = Position::new(z_index, x_index);
 
-                if matches!(block, BlockType::Player) {
-                    player_position = Some(level_position);
+                if let BlockType::Player(index) = block {
+                    player_positions.insert(index, level_position);
                 }
 
                 if matches!(block, BlockType::Exit) {
@@ -70,30 +136,108 @@ impl Level {
             rows.push(row);
         }
 
-        let player_position = player_position.expect("Expect a player position in the level!");
-        let ending_position = ending_position.expect("Expect an ending position in the level!");
-
         Level {
             size: Position::new(x_size, z_size),
             offsets: (x_offset, z_offset),
             rows,
-            player_position,
+            player_positions,
+            exited: Vec::new(),
             ending_position,
             enemy_positions: HashMap::new(),
             coin_positions: HashMap::new(),
             bombs: HashMap::new(),
-            bomb_size: 3,
+            occupancy: Occupancy::default(),
+            bomb_size: BOMB_RANGE,
             ending_visible: false,
             done_loading: false,
             finished: false,
+            stats: LevelStats::default(),
+            endings: Vec::new(),
+            restored: None,
+            theme: DEFAULT_THEME,
         }
     }
 
+    /// The rows of the level in the symbols of the level data, with only the
+    /// walls and the exit. Everything else moves or disappears
+    pub fn layout(&self) -> Vec<String> {
+        self.rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|block| match block.kind {
+                        kind if kind.is_wall() || kind == BlockType::Exit => kind.symbol(),
+                        _ => BlockType::Space.symbol(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// `fuse` returns the remaining time of a bomb
+    pub fn state(&self, fuse: impl Fn(Entity) -> Option<f32>) -> LevelState {
+        LevelState {
+            players: self
+                .player_positions
+                .iter()
+                .map(|(index, position)| (*index, *position))
+                .collect(),
+            exited: self.exited.clone(),
+            enemies: self.enemy_positions.values().copied().collect(),
+            coins: self.coin_positions.values().copied().collect(),
+            bombs: self
+                .bombs
+                .iter()
+                .filter_map(|(entity, (range, position))| {
+                    fuse(*entity).map(|fuse| (*range, *position, fuse))
+                })
+                .collect(),
+            ending_visible: self.ending_visible,
+            stats: self.stats.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, state: LevelState) {
+        self.player_positions = state.players.iter().copied().collect();
+        self.exited = state.exited.clone();
+        self.ending_visible = state.ending_visible;
+        self.stats = state.stats.clone();
+        self.restored = Some(state);
+    }
+
+    /// The players standing on the position
+    pub fn players_at(&self, position: Position) -> Vec<usize> {
+        self.player_positions
+            .iter()
+            .filter(|(_, p)| **p == position)
+            .map(|(index, _)| *index)
+            .collect()
+    }
+
+    /// Returns false if the player was already dead
+    pub fn kill_player(&mut self, index: usize) -> bool {
+        self.player_positions.remove(&index).is_some()
+    }
+
+    /// The player left through the exit and is out of harm's way
+    pub fn exit_player(&mut self, index: usize) {
+        self.player_positions.remove(&index);
+        self.exited.push(index);
+    }
+
     pub fn rows(&self) -> impl Iterator<Item = &Vec<Block>> {
         self.rows.iter()
     }
 
-    fn get(&self, ax: i8, az: i8) -> Option<Block> {
+    /// The number of blocks that can be walked on
+    pub fn free_tiles(&self) -> usize {
+        self.rows()
+            .flat_map(|row| row.iter())
+            .filter(|block| !block.kind.is_wall())
+            .count()
+    }
+
+    fn get(&self, ax: isize, az: isize) -> Option<Block> {
         if ax < 0 || az < 0 {
             return None;
         }
@@ -104,8 +248,99 @@ impl Level {
         Some(item)
     }
 
+    pub fn enemy_positions(&self) -> &HashMap<Entity, Position> {
+        &self.enemy_positions
+    }
+
+    pub fn coin_positions(&self) -> &HashMap<Entity, Position> {
+        &self.coin_positions
+    }
+
+    /// The range and position of every bomb
+    #[cfg(test)]
+    pub fn bombs(&self) -> &HashMap<Entity, (usize, Position)> {
+        &self.bombs
+    }
+
+    /// Adds the enemy or moves it to the position
+    pub fn set_enemy(&mut self, entity: Entity, position: Position) {
+        match self.enemy_positions.insert(entity, position) {
+            Some(previous) if previous == position => return,
+            Some(previous) => self.occupancy.remove(Occupant::Enemy, entity, previous),
+            None => {}
+        }
+        self.occupancy.insert(Occupant::Enemy, entity, position);
+    }
+
+    /// Returns false if the enemy was already dead
+    pub fn remove_enemy(&mut self, entity: Entity) -> bool {
+        match self.enemy_positions.remove(&entity) {
+            Some(position) => {
+                self.occupancy.remove(Occupant::Enemy, entity, position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn enemies_at(&self, position: Position) -> Vec<Entity> {
+        self.occupancy.at(Occupant::Enemy, position).collect()
+    }
+
+    pub fn add_coin(&mut self, entity: Entity, position: Position) {
+        self.coin_positions.insert(entity, position);
+        self.occupancy.insert(Occupant::Coin, entity, position);
+    }
+
+    pub fn remove_coin(&mut self, entity: Entity) {
+        if let Some(position) = self.coin_positions.remove(&entity) {
+            self.occupancy.remove(Occupant::Coin, entity, position);
+        }
+    }
+
+    pub fn coins_at(&self, position: Position) -> Vec<Entity> {
+        self.occupancy.at(Occupant::Coin, position).collect()
+    }
+
+    /// Returns the number of coins that were left
+    pub fn clear_coins(&mut self) -> usize {
+        self.occupancy.clear(Occupant::Coin);
+        self.coin_positions.drain().count()
+    }
+
     pub fn place_bomb(&mut self, entity: Entity, position: Position) {
-        self.bombs.insert(entity, (self.bomb_size, position));
+        self.insert_bomb(entity, self.bomb_size, position);
+    }
+
+    pub fn insert_bomb(&mut self, entity: Entity, range: usize, position: Position) {
+        self.bombs.insert(entity, (range, position));
+        self.occupancy.insert(Occupant::Bomb, entity, position);
+    }
+
+    /// Returns false if the bomb was already gone
+    pub fn remove_bomb(&mut self, entity: Entity) -> bool {
+        match self.bombs.remove(&entity) {
+            Some((_, position)) => {
+                self.occupancy.remove(Occupant::Bomb, entity, position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn bomb_at(&self, position: Position) -> Option<Entity> {
+        self.occupancy.at(Occupant::Bomb, position).next()
+    }
+
+    /// Follows bombs that were spawned again with new entities
+    pub fn remap_bombs(&mut self, respawned: &HashMap<Entity, Entity>) {
+        let map = |entity| respawned.get(&entity).copied().unwrap_or(entity);
+        self.bombs = self
+            .bombs
+            .drain()
+            .map(|(entity, bomb)| (map(entity), bomb))
+            .collect();
+        self.occupancy.remap(Occupant::Bomb, map);
     }
 
     // All positions where the bomb will go except for walls
@@ -118,15 +353,17 @@ impl Level {
         let mut results = vec![(*position, 0, *range)];
         fn follow_range(
             level: &Level,
-            range: i8,
+            range: usize,
             position: Position,
             direction: BoardDirection,
             into: &mut Vec<(Position, usize, usize)>,
         ) {
             let mut current_range = 1;
             loop {
-                let current = direction * current_range;
-                let (x, z) = (position.x as i8 + current.x, position.z as i8 + current.z);
+                let (x, z) = (
+                    position.x as isize + direction.x as isize * current_range as isize,
+                    position.z as isize + direction.z as isize * current_range as isize,
+                );
                 let item = match level.get(x, z) {
                     Some(n) => n,
                     None => break,
@@ -134,11 +371,7 @@ impl Level {
                 if item.kind.is_wall() {
                     break;
                 }
-                into.push((
-                    Position::new(x as usize, z as usize),
-                    current_range as usize,
-                    range as usize,
-                ));
+                into.push((Position::new(x as usize, z as usize), current_range, range));
                 current_range += 1;
                 if range == current_range {
                     break;
@@ -148,28 +381,28 @@ impl Level {
         // go in all 4 directions
         follow_range(
             self,
-            *range as i8,
+            *range,
             *position,
             BoardDirection::new(-1, 0),
             &mut results,
         );
         follow_range(
             self,
-            *range as i8,
+            *range,
             *position,
             BoardDirection::new(0, -1),
             &mut results,
         );
         follow_range(
             self,
-            *range as i8,
+            *range,
             *position,
             BoardDirection::new(1, 0),
             &mut results,
         );
         follow_range(
             self,
-            *range as i8,
+            *range,
             *position,
             BoardDirection::new(0, 1),
             &mut results,
@@ -192,10 +425,10 @@ impl Level {
     /// Find all free spaces (e.g. not walls) around a position
     pub fn free_directions(&self, position: Position) -> Vec<BoardDirection> {
         // traverse all directions around the position and check if they're free
-        let (x, z) = (position.x as i8, position.z as i8);
+        let (x, z) = (position.x as isize, position.z as isize);
         let mut results = Vec::new();
         'outer: for (mx, mz) in [(1_i8, 0), (-1_i8, 0), (0, 1), (0, -1_i8)] {
-            let item = match self.get(x + mx, z + mz) {
+            let item = match self.get(x + mx as isize, z + mz as isize) {
                 Some(n) => n,
                 None => continue,
             };
@@ -212,7 +445,7 @@ impl Level {
     pub fn wall_positions(&self, position: Position) -> Vec<Position> {
         let mut new_position = position;
         new_position.apply_direction(&BoardDirection::new(0, 1));
-        let block = match self.get(new_position.x as i8, new_position.z as i8) {
+        let block = match self.get(new_position.x as isize, new_position.z as isize) {
             Some(n) => n,
             None => return Vec::new(),
         };
@@ -236,7 +469,7 @@ impl Level {
                     continue;
                 }
                 tested.insert(new);
-                let block = match level.get(new.x as i8, new.z as i8) {
+                let block = match level.get(new.x as isize, new.z as isize) {
                     Some(n) => n,
                     None => {
                         continue;
@@ -272,4 +505,84 @@ mod tests {
         let pos = level.wall_positions(Position::new(0, 0));
         assert_eq!(pos.len(), 15);
     }
+
+    #[test]
+    fn test_layout() {
+        let level = Level::new_data("#o*e|\n#x-O#\n");
+        assert_eq!(level.layout(), vec!["#  e|", "# - #"]);
+    }
+
+    #[test]
+    fn test_occupancy_follows_enemies() {
+        let mut level = Level::new_data("o   \n    \n");
+        let enemy = Entity::from_raw(1);
+        level.set_enemy(enemy, Position::new(1, 0));
+        level.set_enemy(enemy, Position::new(2, 1));
+        assert!(level.enemies_at(Position::new(1, 0)).is_empty());
+        assert_eq!(level.enemies_at(Position::new(2, 1)), vec![enemy]);
+        assert!(level.remove_enemy(enemy));
+        assert!(!level.remove_enemy(enemy));
+        assert!(level.enemies_at(Position::new(2, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_positions_beyond_i8() {
+        let data = format!("o{}#\n", " ".repeat(200));
+        let mut level = Level::new_data(&data);
+        assert_eq!(level.free_directions(Position::new(150, 0)).len(), 2);
+        let bomb = Entity::from_raw(1);
+        level.bomb_size = 150;
+        level.place_bomb(bomb, Position::new(150, 0));
+        assert_eq!(level.bomb_explode_positions(bomb).len(), 200);
+    }
+
+    #[test]
+    fn test_positions_at_the_edge() {
+        let mut level = Level::new_data(" o #\n");
+        assert_eq!(level.free_directions(Position::new(0, 0)).len(), 1);
+        let bomb = Entity::from_raw(1);
+        level.bomb_size = 3;
+        level.place_bomb(bomb, Position::new(1, 0));
+        let mut positions: Vec<_> = level
+            .bomb_explode_positions(bomb)
+            .into_iter()
+            .map(|(position, _, _)| position)
+            .collect();
+        positions.sort_by_key(|position| position.x);
+        let expected = [0, 1, 2].map(|x| Position::new(x, 0));
+        assert_eq!(positions, expected);
+        let mut position = Position::new(0, 0);
+        position.apply_direction(&BoardDirection::new(-1, -1));
+        assert_eq!(position, Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_campaign_players() {
+        for level in 0..LEVELS.len() {
+            assert_eq!(Level::new(level, 1).player_positions.len(), 1);
+            assert_eq!(Level::new(level, 2).player_positions.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_restore_exited_partner() {
+        let mut level = Level::new(0, 2);
+        level.exit_player(1);
+        let data = ron::to_string(&level.state(|_| None)).unwrap();
+
+        let mut resumed = Level::new(0, 2);
+        resumed.restore(ron::from_str(&data).unwrap());
+        assert_eq!(resumed.exited, vec![1]);
+        assert_eq!(resumed.player_positions.len(), 1);
+        assert!(resumed.player_positions.contains_key(&0));
+    }
+
+    #[test]
+    fn test_arena_players() {
+        for arena in 0..ARENAS.len() {
+            let level = Level::arena(arena);
+            assert_eq!(level.player_positions.len(), 2);
+            assert!(level.ending_position.is_none());
+        }
+    }
 }
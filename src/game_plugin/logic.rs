@@ -7,24 +7,60 @@ use bevy_tweening::{
 use std::{cmp::Ordering, f32::consts::TAU, time::Duration};
 
 use crate::{
+    actions_plugin::PlayerActions,
+    settings::Action,
     types::{AudioHandles, CurrentMusic},
     GameState, MaterialHandles, MeshHandles,
 };
 
-use super::statics::{self, sizes, FPS, LEVEL_BOMBS, USER_DIED_PAYLOAD};
+use super::effects::{Effect, Effects};
+use super::geometry::setup_level_geometry;
+use super::level::Level;
+use super::particles::{BurstKind, ParticleBurst};
+use super::resume::ResumeRun;
+use super::scoring::POINTS_PER_KILL;
+use super::statics::{
+    self, sizes, ANIMATION_DONE, ENTER_EXIT_SECONDS, EXPLOSION_SECONDS, FPS, LEVEL_BOMBS,
+    PLAYER_GONE_SECONDS,
+};
 use super::types::*;
-use super::{level::Level, statics::LEVEL_COMPLETED_PAYLOAD};
+use super::versus::player_score;
 
+#[allow(clippy::too_many_arguments)]
 pub fn first_level(
     mut commands: Commands,
     audio_sinks: Res<Assets<AudioSink>>,
     audio: Res<Audio>,
     mut playback: ResMut<CurrentMusic>,
     audio_handles: Res<AudioHandles>,
+    start: Res<StartLevel>,
+    custom: Res<CustomLevel>,
+    mut resume: ResMut<ResumeRun>,
+    mode: Res<GameMode>,
+    versus: Res<Versus>,
 ) {
-    commands.insert_resource(super::level::Level::new(0));
-    commands.insert_resource(CurrentLevel(0));
-    commands.insert_resource(super::types::Score::default());
+    match resume.0.take() {
+        _ if *mode == GameMode::Versus => {
+            commands.insert_resource(super::level::Level::arena(versus.arena()));
+            commands.insert_resource(CurrentLevel(versus.arena()));
+        }
+        Some(run) => {
+            let mut level = super::level::Level::new(run.level, mode.players());
+            level.restore(run.state);
+            commands.insert_resource(level);
+            commands.insert_resource(CurrentLevel(run.level));
+            commands.insert_resource(run.score);
+        }
+        None => {
+            let level = match &custom.0 {
+                Some(data) => super::level::Level::from_data(data, mode.players()),
+                None => super::level::Level::new(start.0, mode.players()),
+            };
+            commands.insert_resource(level);
+            commands.insert_resource(CurrentLevel(start.0));
+            commands.insert_resource(super::types::Score::new(start.0));
+        }
+    }
 
     if let Some(sink) = audio_sinks.get(&playback.0) {
         sink.stop();
@@ -62,46 +98,47 @@ pub fn level_loading(
 
     let mut children = Vec::new();
 
+    // a resumed level spawns the moving parts from its stored state
+    let restored = level.restored.take();
+
+    children.extend(setup_level_geometry(
+        &mut commands,
+        &mut meshes,
+        &mesh_handles,
+        &material_handles,
+        &level,
+    ));
+
     for row in level.rows() {
         for block in row.iter() {
-            // Each entry also needs a floor
+            // The exit has a floor of its own, until it opened
             let is_exit = matches!(block.kind, BlockType::Exit);
-            children.push(setup_space(
-                &mut commands,
-                &mesh_handles,
-                &material_handles,
-                (block.position.x, block.position.z),
-                is_exit,
-            ));
-            match block.kind {
-                BlockType::WallBig => children.push(setup_wall(
+            if is_exit && !level.ending_visible {
+                children.push(setup_exit_floor(
                     &mut commands,
-                    mesh_handles.wall.clone(),
+                    &mesh_handles,
                     &material_handles,
-                    block,
-                )),
-                BlockType::WallSmallV => children.push(setup_wall(
-                    &mut commands,
-                    mesh_handles.wall_v.clone(),
-                    &material_handles,
-                    block,
-                )),
-                BlockType::WallSmallH => children.push(setup_wall(
-                    &mut commands,
-                    mesh_handles.wall_h.clone(),
-                    &material_handles,
-                    block,
-                )),
+                    (block.position.x, block.position.z),
+                ));
+            }
+            match block.kind {
+                BlockType::Coin | BlockType::Player(_) | BlockType::Enemy if restored.is_some() => {
+                }
+                // the spawn of a player that doesn't take part
+                BlockType::Player(index) if !level.player_positions.contains_key(&index) => {}
+                // merged into the geometry of the level
+                BlockType::WallBig | BlockType::WallSmallV | BlockType::WallSmallH => {}
                 BlockType::Coin => {
                     let id = setup_coin(&mut commands, &mesh_handles, &material_handles, block);
                     coins.push((id, block.level_position));
                     children.push(id);
                 }
-                BlockType::Player => children.push(setup_player(
+                BlockType::Player(index) => children.push(setup_player(
                     &mut commands,
                     &mut meshes,
                     &material_handles,
                     block,
+                    index,
                 )),
                 BlockType::Enemy => {
                     let id = setup_enemy(&mut commands, &mesh_handles, &material_handles, block);
@@ -120,7 +157,9 @@ pub fn level_loading(
                                 ..default()
                             },
                             transform: Transform::from_xyz(p.x, 0.2, p.z).looking_at(p, Vec3::Z),
-                            visibility: Visibility { is_visible: false },
+                            visibility: Visibility {
+                                is_visible: level.ending_visible,
+                            },
                             ..default()
                         })
                         .insert(ExitLight)
@@ -131,12 +170,53 @@ pub fn level_loading(
         }
     }
 
+    if let Some(state) = restored {
+        let block = |kind, level_position| Block {
+            kind,
+            position: level.translate_from_position(level_position),
+            level_position,
+        };
+        for (index, position) in state.players {
+            children.push(setup_player(
+                &mut commands,
+                &mut meshes,
+                &material_handles,
+                &block(BlockType::Player(index), position),
+                index,
+            ));
+        }
+        for position in state.enemies {
+            let enemy = block(BlockType::Enemy, position);
+            let id = setup_enemy(&mut commands, &mesh_handles, &material_handles, &enemy);
+            enemies.push((id, position));
+            children.push(id);
+        }
+        for position in state.coins {
+            let coin = block(BlockType::Coin, position);
+            let id = setup_coin(&mut commands, &mesh_handles, &material_handles, &coin);
+            coins.push((id, position));
+            children.push(id);
+        }
+        for (range, position, fuse) in state.bombs {
+            let id = add_bomb(
+                &mut commands,
+                &mut meshes,
+                &material_handles,
+                position,
+                level.translate_from_position(position),
+                0,
+            );
+            commands.entity(id).insert(Bomb(fuse));
+            level.insert_bomb(id, range, position);
+        }
+    }
+
     for (id, pos) in enemies {
-        level.enemy_positions.insert(id, pos);
+        level.set_enemy(id, pos);
     }
 
     for (id, pos) in coins {
-        level.coin_positions.insert(id, pos);
+        level.add_coin(id, pos);
     }
 
     for id in children {
@@ -155,6 +235,7 @@ pub fn finish_level(
     current: ResMut<CurrentLevel>,
     mut app_state: ResMut<State<GameState>>,
     mut score: ResMut<Score>,
+    mode: Res<GameMode>,
 ) {
     for _ in reader.iter() {
         for entity in query.iter() {
@@ -172,7 +253,7 @@ pub fn finish_level(
         // replenish the bombs
         score.bombs = LEVEL_BOMBS[next.0];
 
-        commands.insert_resource(super::level::Level::new(next.0));
+        commands.insert_resource(super::level::Level::new(next.0, mode.players()));
         commands.insert_resource(next);
     }
 }
@@ -183,27 +264,6 @@ pub fn cleanup_level(mut commands: Commands, query: Query<Entity, With<LevelItem
     }
 }
 
-pub fn setup_wall(
-    commands: &mut Commands,
-    mesh: Handle<Mesh>,
-    materials: &MaterialHandles,
-    block: &Block,
-) -> Entity {
-    let p = block.position;
-    let s = block.kind.size();
-    commands
-        .spawn_bundle(PbrBundle {
-            mesh,
-            material: materials.wall_normal.clone(),
-            transform: Transform::from_xyz(p.x, p.y, p.z),
-            ..default()
-        })
-        .insert(Size(s))
-        .insert(Location(block.level_position))
-        .insert(Wall)
-        .id()
-}
-
 pub fn setup_coin(
     commands: &mut Commands,
     meshes: &MeshHandles,
@@ -228,6 +288,7 @@ pub fn setup_player(
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &MaterialHandles,
     block: &Block,
+    index: usize,
 ) -> Entity {
     let s = block.kind.size();
     let p = block.position;
@@ -239,23 +300,26 @@ pub fn setup_player(
     let id = commands
         .spawn_bundle(PbrBundle {
             mesh: meshes.add(player_mesh),
-            material: materials.player.clone(),
+            material: materials.players[index].clone(),
             transform: Transform::from_xyz(p.x, 1.0, p.z),
             ..default()
         })
         .insert_bundle(OutlineBundle {
             outline: Outline {
                 visible: true,
-                colour: Color::rgba(0.0, 1.0, 0.0, 1.0),
+                // the theme colours it, see `outline_players`
+                colour: Color::WHITE,
                 width: 1.0,
             },
             ..default()
         })
         .insert(Size(s))
         .insert(Movement::default())
+        .insert(TurnBuffer::default())
         .insert(Location(block.level_position))
         .insert(Speed(statics::PLAYER_SPEED))
-        .insert(Player)
+        .insert(Player(index))
+        .insert(Actor)
         .id();
     // add a tween so the player falls into the game
     let tween = Tween::new(
@@ -326,7 +390,8 @@ pub fn setup_enemy(
         .insert(Movement::default())
         .insert(Location(block.level_position))
         .insert(Speed(statics::ENEMY_SPEED_EASY))
-        .insert(Enemy);
+        .insert(Enemy)
+        .insert(Actor);
     parent
 }
 
@@ -336,6 +401,7 @@ pub fn add_bomb(
     materials: &MaterialHandles,
     level_position: Position,
     position: Vec3,
+    owner: usize,
 ) -> Entity {
     let mesh = Mesh::from(shape::Cube {
         size: sizes::bomb_size,
@@ -372,14 +438,20 @@ pub fn add_bomb(
         .entity(parent)
         .push_children(&[head, fire])
         .insert(Location(level_position))
-        .insert(Bomb::new());
+        .insert(Bomb::new())
+        .insert(BombOwner(owner))
+        .insert(LevelItem);
     parent
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn add_bomb_explosion(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &MaterialHandles,
+    bomb: Entity,
+    owner: usize,
+    ticks: u32,
     level_position: Position,
     position: Vec3,
 ) -> Entity {
@@ -395,16 +467,17 @@ pub fn add_bomb_explosion(
             ..default()
         })
         .insert(Location(level_position))
-        .insert(BombExplosion)
+        .insert(BombExplosion { bomb, owner, ticks })
+        .insert(LevelItem)
         .id()
 }
 
-pub fn setup_space(
+/// The floor of the exit, which drops away when the exit opens
+pub fn setup_exit_floor(
     commands: &mut Commands,
     meshes: &MeshHandles,
     materials: &MaterialHandles,
     position: (f32, f32),
-    hides_exit: bool,
 ) -> Entity {
     let parent = commands
         .spawn_bundle(PbrBundle {
@@ -414,12 +487,9 @@ pub fn setup_space(
             ..default()
         })
         .insert(Floor)
+        .insert(Exit)
         .id();
 
-    if hides_exit {
-        commands.entity(parent).insert(Exit);
-    }
-
     let child1 = commands
         .spawn_bundle(PbrBundle {
             mesh: meshes.floor_bg.clone(),
@@ -465,13 +535,12 @@ pub fn wobble_enemy(mut query: Query<&mut Transform, With<Enemy>>, timer: Res<Ti
 pub fn enemy_logic(
     mut query: Query<(&mut Movement, &Transform, &Location, &Speed), With<Enemy>>,
     level: Res<Level>,
-    player_query: Query<&Transform, With<Player>>,
+    player_query: Query<&Transform, (With<Player>, With<Movement>)>,
 ) {
-    // find the player location
-    let player_location = match player_query.iter().next() {
-        Some(n) => Vec2::new(n.translation.x, n.translation.z),
-        None => return,
-    };
+    let player_locations: Vec<_> = player_query
+        .iter()
+        .map(|n| Vec2::new(n.translation.x, n.translation.z))
+        .collect();
 
     for (mut velocity, transform, position, _) in query.iter_mut() {
         // if we're still moving, do nothing
@@ -479,88 +548,150 @@ pub fn enemy_logic(
             continue;
         }
         let v = Vec2::new(transform.translation.x, transform.translation.z);
-        // find the free directions
-        let mut directions = level.free_directions(position.0);
-        if directions.is_empty() {
-            continue;
-        }
-        // just to check if a change by this value brings as closer to the player
-        let mov = Vec2::new(0.05, 0.05);
-
-        // order directions by pointing towards the player
-        directions.sort_unstable_by(|a, b| {
-            // apply the direction and return distance
-            let ax: Vec2 = v + (*a * mov);
-            let bx: Vec2 = v + (*b * mov);
-            ax.distance(player_location)
-                .partial_cmp(&bx.distance(player_location))
-                .unwrap_or(Ordering::Equal)
-        });
+        let direction = match enemy_direction(&level, position.0, v, &player_locations) {
+            Some(n) => n,
+            None => continue,
+        };
 
         // calculate the new velocity value based on the current speed and time
         // the size of the field on the timestep and the speed step
         // let frames = FPS * speed.0;
         // let value = sizes::field.x / frames;
-        velocity.direction = directions[0];
+        velocity.direction = direction;
         velocity.value = sizes::field.x;
     }
 }
 
+/// The free direction of an enemy at `position` and `v` that points the most
+/// towards the closest of the players
+pub fn enemy_direction(
+    level: &Level,
+    position: Position,
+    v: Vec2,
+    player_locations: &[Vec2],
+) -> Option<BoardDirection> {
+    // hunt the closest player
+    let player_location = *player_locations.iter().min_by(|a, b| {
+        a.distance(v)
+            .partial_cmp(&b.distance(v))
+            .unwrap_or(Ordering::Equal)
+    })?;
+    // find the free directions
+    let mut directions = level.free_directions(position);
+    // just to check if a change by this value brings as closer to the player
+    let mov = Vec2::new(0.05, 0.05);
+
+    // order directions by pointing towards the player
+    directions.sort_unstable_by(|a, b| {
+        // apply the direction and return distance
+        let ax: Vec2 = v + (*a * mov);
+        let bx: Vec2 = v + (*b * mov);
+        ax.distance(player_location)
+            .partial_cmp(&bx.distance(player_location))
+            .unwrap_or(Ordering::Equal)
+    });
+    directions.first().copied()
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn keyboard_input_system(
     mut commands: Commands,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(&mut Movement, &Location), With<Player>>,
+    actions: Res<PlayerActions>,
+    mut query: Query<(&Player, &mut Movement, &mut TurnBuffer, &Location)>,
     mut level: ResMut<Level>,
+    mode: Res<GameMode>,
     mut score: ResMut<Score>,
+    mut versus: ResMut<Versus>,
     material_handles: Res<MaterialHandles>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    for (mut velocity, location) in query.iter_mut() {
+    // the player already left through the exit
+    if level.finished {
+        return;
+    }
+    let moves = [
+        (Action::MoveLeft, BoardDirection::new(-1, 0)),
+        (Action::MoveRight, BoardDirection::new(1, 0)),
+        (Action::MoveUp, BoardDirection::new(0, -1)),
+        (Action::MoveDown, BoardDirection::new(0, 1)),
+    ];
+    for (player, mut velocity, mut buffer, location) in query.iter_mut() {
+        let actions = &actions.0[player.0];
+        let score = player_score(*mode, &mut score, &mut versus, player.0);
+        // if the user tried to place a bomb
+        if actions.just_pressed(Action::PlaceBomb) {
+            place_bomb(
+                &mut commands,
+                &mut meshes,
+                &material_handles,
+                &mut level,
+                score,
+                location.0,
+                player.0,
+            );
+        }
+        // remember the latest request, even while we are still moving, until
+        // it is taken or replaced by the next one
+        for (action, direction) in moves {
+            if actions.just_pressed(action) {
+                buffer.direction = Some(direction);
+            }
+        }
         // if we're in movement, do nothing
         if velocity.value > 0.0 {
             continue;
         }
-        // make sure we only move into directions we can
-        for (code, direction) in [
-            (KeyCode::Left, BoardDirection::new(-1, 0)),
-            (KeyCode::Right, BoardDirection::new(1, 0)),
-            (KeyCode::Up, BoardDirection::new(0, -1)),
-            (KeyCode::Down, BoardDirection::new(0, 1)),
-        ] {
-            if keyboard_input.pressed(code) {
-                let directions = level.free_directions(location.0);
-                if directions.contains(&direction) {
-                    velocity.direction = direction;
-                    velocity.value = sizes::field.x;
-                    score.moves += 1;
-                }
+        // make sure we only move into directions we can, a buffered turn wins over held keys
+        let directions = level.free_directions(location.0);
+        let held = moves
+            .iter()
+            .filter(|(action, _)| actions.pressed(*action))
+            .map(|(_, direction)| *direction);
+        let next = buffer
+            .direction
+            .into_iter()
+            .chain(held)
+            .find(|direction| directions.contains(direction));
+        if let Some(direction) = next {
+            if buffer.direction == Some(direction) {
+                buffer.direction = None;
             }
+            velocity.direction = direction;
+            velocity.value = sizes::field.x;
+            level.stats.moves += 1;
         }
     }
-    // if the user tried to place a bomb
-    let level_position = level.player_position;
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        // if we don't have bombs left
-        if score.bombs == 0 {
-            return;
-        }
-        score.bombs -= 1;
-        // if there is no bomb yet
-        for (_, position) in level.bombs.values() {
-            if &level_position == position {
-                return;
-            }
-        }
-        let position = level.translate_from_position(level_position);
-        let id = add_bomb(
-            &mut commands,
-            &mut meshes,
-            &material_handles,
-            level_position,
-            position,
-        );
-        level.place_bomb(id, level_position);
+}
+
+fn place_bomb(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    material_handles: &MaterialHandles,
+    level: &mut Level,
+    score: &mut Score,
+    level_position: Position,
+    owner: usize,
+) {
+    // if we don't have bombs left
+    if score.bombs == 0 {
+        return;
     }
+    // if there is no bomb yet
+    if level.bomb_at(level_position).is_some() {
+        return;
+    }
+    let position = level.translate_from_position(level_position);
+    let id = add_bomb(
+        commands,
+        meshes,
+        material_handles,
+        level_position,
+        position,
+        owner,
+    );
+    level.place_bomb(id, level_position);
+    score.bombs -= 1;
+    level.stats.bombs_used += 1;
 }
 
 pub fn move_entities(
@@ -574,8 +705,7 @@ pub fn move_entities(
             velocity.direction = BoardDirection::default();
             continue;
         }
-        let frames = FPS * speed.0;
-        let value = sizes::field.x / frames;
+        let value = move_step(speed.0);
         let vector = velocity.direction * Vec2::new(1.0, 1.0) * value;
         let new_translation = Vec3::new(
             vector.x + transform.translation.x,
@@ -598,30 +728,15 @@ pub fn move_entities(
     }
 }
 
-pub fn wall_visibility(
-    mut commands: Commands,
-    query: Query<(Entity, &Location), With<Wall>>,
-    level: Res<Level>,
-    player_query: Query<&Location, (With<Player>, Changed<Location>)>,
-    materials: Res<MaterialHandles>,
-) {
-    let player_location = match player_query.iter().next() {
-        Some(n) => n,
-        None => return,
-    };
-    let walls_below = level.wall_positions(player_location.0);
-    for (entity, location) in query.iter() {
-        if walls_below.contains(&location.0) {
-            commands
-                .entity(entity)
-                .remove::<Handle<StandardMaterial>>()
-                .insert(materials.wall_hidden.clone());
-        } else {
-            commands
-                .entity(entity)
-                .remove::<Handle<StandardMaterial>>()
-                .insert(materials.wall_normal.clone());
-        }
+/// How far an entity of the speed moves in one frame
+pub fn move_step(speed: f32) -> f32 {
+    sizes::field.x / (FPS * speed)
+}
+
+/// Measures how long the player needs for the current level
+pub fn level_timer(mut level: ResMut<Level>) {
+    if level.done_loading && !level.finished {
+        level.stats.time += 1.0 / FPS;
     }
 }
 
@@ -631,47 +746,70 @@ pub fn wall_visibility(
 pub fn update_level(
     mut commands: Commands,
     mut level: ResMut<Level>,
-    player_query: Query<(Entity, &Location, &Transform), (With<Player>, Changed<Location>)>,
+    player_query: Query<(Entity, &Player, &Location, &Transform), Changed<Location>>,
     enemy_query: Query<(Entity, &Location), (With<Enemy>, Changed<Location>)>,
+    mode: Res<GameMode>,
     mut score: ResMut<Score>,
+    mut versus: ResMut<Versus>,
     mut player_sender: EventWriter<PlayerDiedEvent>,
-    audio: Res<Audio>,
+    mut effects: ResMut<Effects>,
     sounds: Res<AudioHandles>,
 ) {
+    if level.finished {
+        return;
+    }
     for (entity, location) in enemy_query.iter() {
-        level.enemy_positions.insert(entity, location.0);
-        if level.player_position == location.0 {
-            player_sender.send(PlayerDiedEvent);
+        level.set_enemy(entity, location.0);
+        for index in level.players_at(location.0) {
+            if level.kill_player(index) {
+                player_sender.send(PlayerDiedEvent(index));
+            }
         }
     }
-    if let Some((player_entity, player_location, player_transform)) = player_query.iter().next() {
-        level.player_position = player_location.0;
+    for (player_entity, player, player_location, player_transform) in player_query.iter() {
+        // dead players don't move anymore
+        match level.player_positions.get_mut(&player.0) {
+            Some(position) => *position = player_location.0,
+            None => continue,
+        }
         // check if player and enemies collide
-        for position in level.enemy_positions.values() {
-            if position == &player_location.0 {
-                player_sender.send(PlayerDiedEvent);
-            }
+        let caught = !level.enemies_at(player_location.0).is_empty();
+        if caught && level.kill_player(player.0) {
+            player_sender.send(PlayerDiedEvent(player.0));
+            continue;
         }
         // check if the player is over the exit
-        if level.ending_position == player_location.0 && level.ending_visible {
-            // somehow jump to the next level
-            player_enter_exit(&mut commands, player_entity, player_transform)
-        }
-        let mut deleted_coins = Vec::new();
-        for (entity, position) in level.coin_positions.iter() {
-            if position == &player_location.0 {
-                destroy_coin(&mut commands, entity);
-                score.coins += 1;
-                deleted_coins.push(*entity);
-                audio.play(sounds.coin.clone());
+        if level.ending_position == Some(player_location.0) && level.ending_visible {
+            level.exit_player(player.0);
+            // the level is done once every player that is still alive left
+            let last = level.player_positions.is_empty();
+            commands.entity(player_entity).remove::<Movement>();
+            effects.push(Effect::EnterExit(player_entity));
+            if last {
+                level
+                    .endings
+                    .push((seconds_to_ticks(ENTER_EXIT_SECONDS), Ending::CompleteLevel));
             }
+            level.finished = last;
+            continue;
         }
-        for coin in deleted_coins {
-            level.coin_positions.remove(&coin);
+        let score = player_score(*mode, &mut score, &mut versus, player.0);
+        for coin in level.coins_at(player_location.0) {
+            destroy_coin(&mut commands, &coin);
+            effects.push(Effect::Despawn(coin));
+            effects.push(Effect::Sound(sounds.coin.clone()));
+            effects.push(Effect::Burst(ParticleBurst {
+                kind: BurstKind::Sparkle,
+                position: player_transform.translation,
+            }));
+            level.remove_coin(coin);
+            score.coins += 1;
+            score.points += level.stats.record_coin();
         }
     }
 }
 
+/// The coin shrinks away right away, it's despawned once a rollback can't bring it back
 fn destroy_coin(commands: &mut Commands, entity: &Entity) {
     let mut tween = Tween::new(
         EaseFunction::QuadraticInOut,
@@ -682,80 +820,182 @@ fn destroy_coin(commands: &mut Commands, entity: &Entity) {
             end: Vec3::ZERO,
         },
     );
-    tween.set_completed_event(0);
+    tween.set_completed_event(ANIMATION_DONE);
     commands.entity(*entity).insert(Animator::new(tween));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn player_did_die_system(
     mut commands: Commands,
-    player: Query<(Entity, &Transform), With<Player>>,
+    players: Query<(Entity, &Player, &Transform)>,
     mut player_reader: EventReader<PlayerDiedEvent>,
-    audio: Res<Audio>,
+    mut level: ResMut<Level>,
+    mode: Res<GameMode>,
+    mut effects: ResMut<Effects>,
     sounds: Res<AudioHandles>,
 ) {
-    for _ in player_reader.iter() {
-        let (entity, transform) = player.single();
-        implode_entity(&mut commands, entity, transform, USER_DIED_PAYLOAD);
+    for PlayerDiedEvent(index) in player_reader.iter() {
+        let player = players.iter().find(|(_, player, _)| player.0 == *index);
+        let (entity, _, transform) = match player {
+            Some(n) => n,
+            None => continue,
+        };
+        level.finished |= match *mode {
+            // the last survivor wins the versus round and can't die anymore
+            GameMode::Versus => level.player_positions.len() <= 1,
+            // the partner already left through the exit
+            _ => level.player_positions.is_empty() && !level.exited.is_empty(),
+        };
+        effects.push(Effect::Implode(entity, false));
+        level
+            .endings
+            .push((seconds_to_ticks(PLAYER_GONE_SECONDS), Ending::PlayerGone));
         commands
             .entity(entity)
             .remove::<Movement>()
             .remove::<Speed>();
-        // send a brief delay before going to loose
-        audio.play(sounds.kill.clone());
+        effects.push(Effect::Sound(sounds.kill.clone()));
+        effects.push(Effect::Burst(ParticleBurst {
+            kind: BurstKind::PlayerDeath(*index),
+            position: transform.translation,
+        }));
+    }
+}
+
+/// Counts down the endings of the level and lets the game know once they are due
+pub fn count_endings(mut level: ResMut<Level>, mut effects: ResMut<Effects>) {
+    for (ticks, ending) in level.endings.iter_mut() {
+        *ticks = ticks.saturating_sub(1);
+        if *ticks == 0 {
+            effects.push(Effect::Ending(*ending));
+        }
     }
+    level.endings.retain(|(ticks, _)| *ticks > 0);
 }
 
-/// This removes all tweens that are done and had a complete handler set up
-pub fn tween_done_remove_handler(
+fn seconds_to_ticks(seconds: f32) -> u32 {
+    (seconds * FPS).round() as u32
+}
+
+/// Plays the sounds, animations and particles the simulation asked for. An entity may be
+/// gone by then, when the level ended in the meantime
+#[allow(clippy::too_many_arguments)]
+pub fn play_effects(
+    mut commands: Commands,
+    mut effects: ResMut<Effects>,
+    transforms: Query<&Transform>,
+    animators: Query<&Animator<Transform>>,
+    audio: Res<Audio>,
+    mut bursts: EventWriter<ParticleBurst>,
+    mut completed_writer: EventWriter<LevelCompletedEvent>,
+    mut gone_writer: EventWriter<PlayerGoneEvent>,
+) {
+    for effect in effects.take_ready() {
+        match effect {
+            Effect::Sound(sound) => {
+                audio.play(sound);
+            }
+            Effect::Burst(burst) => bursts.send(burst),
+            Effect::Implode(entity, despawn) => {
+                if let Ok(transform) = transforms.get(entity) {
+                    implode_entity(&mut commands, entity, transform, despawn);
+                }
+            }
+            Effect::EnterExit(entity) => {
+                if let Ok(transform) = transforms.get(entity) {
+                    player_enter_exit(&mut commands, entity, transform);
+                }
+            }
+            Effect::Despawn(entity) => {
+                if transforms.get(entity).is_err() {
+                    continue;
+                }
+                match animators.get(entity) {
+                    Ok(animator) if animator.progress() < 1.0 => {
+                        commands.entity(entity).insert(DespawnAfterAnimation);
+                    }
+                    _ => commands.entity(entity).despawn_recursive(),
+                }
+            }
+            Effect::Ending(Ending::PlayerGone) => gone_writer.send(PlayerGoneEvent),
+            Effect::Ending(Ending::CompleteLevel) => completed_writer.send(LevelCompletedEvent),
+        }
+    }
+}
+
+/// Removes the coins and enemies whose animation is done
+pub fn animation_done(
     mut commands: Commands,
     mut done: EventReader<TweenCompleted>,
-    mut writer: EventWriter<GoNextLevelEvent>,
-    mut app_state: ResMut<State<GameState>>,
+    despawn: Query<(), With<DespawnAfterAnimation>>,
 ) {
     for ev in done.iter() {
-        if ev.user_data == LEVEL_COMPLETED_PAYLOAD {
-            writer.send(GoNextLevelEvent);
-        } else if ev.user_data == USER_DIED_PAYLOAD {
-            app_state.set(GameState::Lost).unwrap();
-        } else {
+        if despawn.contains(ev.entity) {
             commands.entity(ev.entity).despawn_recursive();
         }
     }
 }
 
+/// Once a dead player disappeared, the level or the versus round may be over
+pub fn player_gone_system(
+    mut gone: EventReader<PlayerGoneEvent>,
+    mut writer: EventWriter<LevelCompletedEvent>,
+    mut round_writer: EventWriter<RoundOverEvent>,
+    mut app_state: ResMut<State<GameState>>,
+    level: Res<Level>,
+    mode: Res<GameMode>,
+) {
+    for _ in gone.iter() {
+        match *mode {
+            GameMode::Versus if level.finished => round_writer.send(RoundOverEvent),
+            // a player that died while others are left just disappears
+            _ if !level.player_positions.is_empty() => {}
+            GameMode::Versus => {}
+            _ if level.exited.is_empty() => app_state.set(GameState::Lost).unwrap(),
+            _ => writer.send(LevelCompletedEvent),
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn bomb_counter(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut Bomb, &mut Transform)>,
-    time: Res<Time>,
+    mut query: Query<(Entity, &mut Bomb, &BombOwner, &mut Transform)>,
     mut level: ResMut<Level>,
     material_handles: Res<MaterialHandles>,
     mut meshes: ResMut<Assets<Mesh>>,
-    audio: Res<Audio>,
+    mut effects: ResMut<Effects>,
     sounds: Res<AudioHandles>,
 ) {
-    let change = time.delta_seconds();
-    for (entity, mut bomb, mut transform) in query.iter_mut() {
-        bomb.0 -= change;
+    let change = 1.0 / FPS;
+    for (entity, mut bomb, owner, mut transform) in query.iter_mut() {
+        bomb.0 = burn_fuse(bomb.0);
         if bomb.0 <= 0.0 {
             commands.entity(entity).despawn_recursive();
             // spawn the explosions
             for (level_position, strength, max) in level.bomb_explode_positions(entity) {
-                let delay_sec = (strength as f32 / max as f32) / 2.0;
+                let delay_sec = explosion_delay(strength, max);
+                let ticks = explosion_ticks(strength, max);
                 let position = level.translate_from_position(level_position);
                 let id = add_bomb_explosion(
                     &mut commands,
                     &mut meshes,
                     &material_handles,
+                    entity,
+                    owner.0,
+                    ticks,
                     level_position,
                     position,
                 );
                 insert_bomb_explosion_tween(&mut commands, id, delay_sec);
             }
-            if level.bombs.contains_key(&entity) {
-                audio.play(sounds.explosion.clone());
+            if level.remove_bomb(entity) {
+                effects.push(Effect::Sound(sounds.explosion.clone()));
+                effects.push(Effect::Burst(ParticleBurst {
+                    kind: BurstKind::Debris,
+                    position: transform.translation,
+                }));
             }
-            level.bombs.remove(&entity);
         } else if bomb.0 <= 0.5 {
             // the closer to zero we get, the more the bomb shakes
             transform.translation.y = change.sin() * 10.;
@@ -763,43 +1003,84 @@ pub fn bomb_counter(
     }
 }
 
+/// What is left of a fuse after a frame. Like the movement, fuses advance a
+/// fixed step per frame, which keeps online games in sync
+pub fn burn_fuse(fuse: f32) -> f32 {
+    fuse - 1.0 / FPS
+}
+
+/// The seconds until an explosion reaches a tile `strength` tiles from its bomb
+pub fn explosion_delay(strength: usize, max: usize) -> f32 {
+    (strength as f32 / max as f32) / 2.0
+}
+
+/// The frames an explosion tile burns, `strength` tiles from its bomb
+pub fn explosion_ticks(strength: usize, max: usize) -> u32 {
+    ((explosion_delay(strength, max) + EXPLOSION_SECONDS) * FPS).round() as u32
+}
+
+/// Removes the explosion tiles once they burned out
+pub fn explosion_timer(mut commands: Commands, mut query: Query<(Entity, &mut BombExplosion)>) {
+    for (entity, mut explosion) in query.iter_mut() {
+        explosion.ticks = explosion.ticks.saturating_sub(1);
+        if explosion.ticks == 0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 // if enemy or player interacts with a bomb explosion, remove them
 #[allow(clippy::too_many_arguments)]
 pub fn bomb_explosion_destruction(
     mut commands: Commands,
-    explosion_query: Query<(Entity, &Location), With<BombExplosion>>,
-    enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+    explosion_query: Query<(&BombExplosion, &Location)>,
+    enemy_query: Query<&Transform, With<Enemy>>,
     mut level: ResMut<Level>,
+    mode: Res<GameMode>,
+    mut score: ResMut<Score>,
+    mut versus: ResMut<Versus>,
     mut level_exit_writer: EventWriter<ShowLevelExitEvent>,
     mut player_sender: EventWriter<PlayerDiedEvent>,
-    audio: Res<Audio>,
+    mut effects: ResMut<Effects>,
     sounds: Res<AudioHandles>,
 ) {
-    let mut removable_enemies = Vec::new();
-    for (_, location) in explosion_query.iter() {
-        if level.player_position == location.0 {
-            player_sender.send(PlayerDiedEvent);
-        }
-        for (entity, transform) in enemy_query.iter() {
-            if level.enemy_positions[&entity] == location.0 {
-                implode_entity(&mut commands, entity, transform, 0);
-                removable_enemies.push(entity);
-                commands
-                    .entity(entity)
-                    .remove::<Enemy>()
-                    .remove::<Movement>()
-                    .remove::<Speed>();
+    for (explosion, location) in explosion_query.iter() {
+        for index in level.players_at(location.0) {
+            if level.finished || !level.kill_player(index) {
+                continue;
+            }
+            player_sender.send(PlayerDiedEvent(index));
+            // blowing up the opponent is worth as much as an enemy
+            if *mode == GameMode::Versus && index != explosion.owner {
+                player_score(*mode, &mut score, &mut versus, explosion.owner).points +=
+                    POINTS_PER_KILL;
             }
         }
-    }
-    for entity in removable_enemies {
-        if level.enemy_positions.contains_key(&entity) {
-            audio.play(sounds.kill.clone());
+        for entity in level.enemies_at(location.0) {
+            let transform = match enemy_query.get(entity) {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+            level.remove_enemy(entity);
+            effects.push(Effect::Implode(entity, true));
+            commands
+                .entity(entity)
+                .remove::<Enemy>()
+                .remove::<Movement>()
+                .remove::<Speed>();
+            effects.push(Effect::Sound(sounds.kill.clone()));
+            effects.push(Effect::Burst(ParticleBurst {
+                kind: BurstKind::EnemyDeath,
+                position: transform.translation,
+            }));
+            player_score(*mode, &mut score, &mut versus, explosion.owner).points +=
+                level.stats.record_kill(explosion.bomb);
         }
-        level.enemy_positions.remove(&entity);
     }
     // if there're no enemies left, start the end level condition
-    if level.enemy_positions.is_empty() && !level.ending_visible && level.done_loading {
+    let has_exit = level.ending_position.is_some();
+    if level.enemy_positions().is_empty() && !level.ending_visible && level.done_loading && has_exit
+    {
         level_exit_writer.send(ShowLevelExitEvent);
         level.ending_visible = true;
     }
@@ -812,9 +1093,14 @@ pub fn show_level_exit(
     coins: Query<Entity, With<Coin>>,
     mut lamps: Query<&mut Visibility, With<ExitLight>>,
     mut level: ResMut<Level>,
+    mut effects: ResMut<Effects>,
 ) {
     for _ in event.iter() {
         for (entity, transform) in exits.iter() {
+            effects.push(Effect::Burst(ParticleBurst {
+                kind: BurstKind::ExitOpened,
+                position: transform.translation,
+            }));
             let tween = Tween::new(
                 EaseFunction::BounceOut,
                 TweeningType::Once,
@@ -835,16 +1121,18 @@ pub fn show_level_exit(
         // Destory all remaining coins
         for entity in coins.iter() {
             destroy_coin(&mut commands, &entity);
+            effects.push(Effect::Despawn(entity));
         }
-        level.coin_positions.clear();
+        level.stats.coins_missed = level.clear_coins();
     }
 }
 
+/// Only shows the player leaving, the level is completed by `count_endings`
 fn player_enter_exit(commands: &mut Commands, entity: Entity, transform: &Transform) {
-    let mut tween = Tween::new(
+    let tween = Tween::new(
         EaseFunction::BounceOut,
         TweeningType::Once,
-        Duration::from_secs_f32(0.7),
+        Duration::from_secs_f32(ENTER_EXIT_SECONDS),
         TransformPositionLens {
             start: transform.translation,
             end: Vec3::new(
@@ -854,15 +1142,15 @@ fn player_enter_exit(commands: &mut Commands, entity: Entity, transform: &Transf
             ),
         },
     );
-    tween.set_completed_event(LEVEL_COMPLETED_PAYLOAD);
     commands
         .entity(entity)
         .remove_bundle::<OutlineBundle>()
         .insert(Animator::new(tween));
 }
 
-fn implode_entity(commands: &mut Commands, entity: Entity, transform: &Transform, payload: u64) {
-    let duration = 0.3;
+fn implode_entity(commands: &mut Commands, entity: Entity, transform: &Transform, despawn: bool) {
+    // the last step takes the rest of the time a dying player needs
+    let duration = PLAYER_GONE_SECONDS - 0.1;
     // We scale the enemy
     let tween1 = Tween::new(
         EaseFunction::BounceOut,
@@ -910,12 +1198,16 @@ fn implode_entity(commands: &mut Commands, entity: Entity, transform: &Transform
             end: Vec3::ZERO,
         },
     );
-    step2.set_completed_event(payload);
+    if despawn {
+        step2.set_completed_event(ANIMATION_DONE);
+        commands.entity(entity).insert(DespawnAfterAnimation);
+    }
     let series = Sequence::from_single(step1).then(step2);
     commands.entity(entity).insert(Animator::new(series));
 }
 
-fn insert_bomb_explosion_tween(commands: &mut Commands, entity: Entity, delay_sec: f32) {
+/// Only shows the explosion, `explosion_timer` removes it
+pub fn insert_bomb_explosion_tween(commands: &mut Commands, entity: Entity, delay_sec: f32) {
     let step = 0.10;
     // build up the explosion tweens
     let tween1 = Tween::new(
@@ -954,7 +1246,7 @@ fn insert_bomb_explosion_tween(commands: &mut Commands, entity: Entity, delay_se
             end: Vec3::new(0.5, 0.5, 0.5),
         },
     );
-    let mut tween5 = Tween::new(
+    let tween5 = Tween::new(
         EaseFunction::BounceInOut,
         TweeningType::Once,
         Duration::from_secs_f32(step),
@@ -963,7 +1255,6 @@ fn insert_bomb_explosion_tween(commands: &mut Commands, entity: Entity, delay_se
             end: Vec3::ZERO,
         },
     );
-    tween5.set_completed_event(0);
     let delay = Delay::new(Duration::from_secs_f32(delay_sec));
     let s = delay
         .then(tween1)
@@ -973,3 +1264,311 @@ fn insert_bomb_explosion_tween(commands: &mut Commands, entity: Entity, delay_se
         .then(tween5);
     commands.entity(entity).insert(Animator::new(s));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_plugin::particles::Particle;
+    use crate::headless_plugin::harness::Harness;
+    use crate::settings::{ParticleQuality, Settings};
+
+    /// The enemies stay where they are, so that they can be hit on purpose
+    fn freeze_enemies(harness: &mut Harness) {
+        let world = &mut harness.app.world;
+        let enemies: Vec<_> = world
+            .query_filtered::<Entity, With<Enemy>>()
+            .iter(world)
+            .collect();
+        for enemy in enemies {
+            world.entity_mut(enemy).remove::<Speed>();
+        }
+    }
+
+    fn player_position(harness: &Harness) -> Option<Position> {
+        harness
+            .resource::<Level>()
+            .player_positions
+            .get(&0)
+            .copied()
+    }
+
+    fn walk(harness: &mut Harness, action: Action, to: Position) {
+        harness.press(0, action);
+        let arrived = harness.run_until(60, |world| {
+            world.resource::<Level>().player_positions.get(&0) == Some(&to)
+        });
+        harness.release(0, action);
+        assert!(arrived, "the player didn't get to {to:?}");
+    }
+
+    fn wait_for_explosion(harness: &mut Harness) {
+        let done = harness.run_until(200, |world| {
+            world.resource::<Level>().bombs().is_empty()
+                && world.query::<&BombExplosion>().iter(world).next().is_none()
+        });
+        assert!(done, "the bomb never went off");
+    }
+
+    #[test]
+    fn test_coin_collected_increments_score() {
+        let mut harness = Harness::new(
+            "
+########
+#o *x e#
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        assert_eq!(harness.resource::<Score>().coins, 0);
+        walk(&mut harness, Action::MoveRight, Position::new(3, 1));
+        harness.tick();
+        let score = harness.resource::<Score>();
+        assert_eq!(score.coins, 1);
+        assert!(score.points > 0);
+        assert!(harness.resource::<Level>().coin_positions().is_empty());
+    }
+
+    #[test]
+    fn test_turn_is_taken_at_the_next_junction() {
+        let mut harness = Harness::new(
+            "
+#########
+#o     e#
+##### ###
+#########
+",
+        );
+        freeze_enemies(&mut harness);
+        harness.press(0, Action::MoveRight);
+        harness.tick();
+        harness.tick();
+        // halfway to the next tile, long before the way down is open
+        harness.tap(0, Action::MoveDown);
+        let turned = harness.run_until(200, |world| {
+            world.resource::<Level>().player_positions.get(&0) == Some(&Position::new(5, 2))
+        });
+        assert!(turned, "the buffered turn was never taken");
+    }
+
+    #[test]
+    fn test_bomb_kills_enemy_at_range_two() {
+        let mut harness = Harness::new(
+            "
+########
+#o  x e#
+# ######
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        walk(&mut harness, Action::MoveRight, Position::new(2, 1));
+        harness.tap(0, Action::PlaceBomb);
+        assert_eq!(harness.resource::<Score>().bombs, LEVEL_BOMBS[0] - 1);
+        walk(&mut harness, Action::MoveLeft, Position::new(1, 1));
+        walk(&mut harness, Action::MoveDown, Position::new(1, 2));
+        wait_for_explosion(&mut harness);
+
+        assert!(harness.resource::<Level>().enemy_positions().is_empty());
+        assert_eq!(player_position(&harness), Some(Position::new(1, 2)));
+    }
+
+    #[test]
+    fn test_coins_and_enemies_are_despawned_offline() {
+        let mut harness = Harness::new(
+            "
+########
+#o* x e#
+# ######
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        walk(&mut harness, Action::MoveRight, Position::new(2, 1));
+        harness.tap(0, Action::PlaceBomb);
+        walk(&mut harness, Action::MoveLeft, Position::new(1, 1));
+        walk(&mut harness, Action::MoveDown, Position::new(1, 2));
+        wait_for_explosion(&mut harness);
+
+        let gone = harness.run_until(60, |world| {
+            world.query::<&Coin>().iter(world).next().is_none()
+                && world.query::<&Actor>().iter(world).count() == 1
+        });
+        assert!(gone, "the coin or the enemy is still around");
+    }
+
+    #[test]
+    fn test_bomb_on_a_bomb_is_not_used_up() {
+        let mut harness = Harness::new(
+            "
+########
+#o  x e#
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        harness.tap(0, Action::PlaceBomb);
+        harness.tick();
+        harness.tap(0, Action::PlaceBomb);
+        assert_eq!(harness.resource::<Score>().bombs, LEVEL_BOMBS[0] - 1);
+        let level = harness.resource::<Level>();
+        assert_eq!(level.bombs().len(), 1);
+        assert_eq!(level.stats.bombs_used, 1);
+    }
+
+    #[test]
+    fn test_exit_opens_when_last_enemy_dies() {
+        let mut harness = Harness::new(
+            "
+#########
+#x  o  x#
+#### ####
+####e####
+#########
+",
+        );
+        freeze_enemies(&mut harness);
+        let pocket = Position::new(4, 2);
+
+        walk(&mut harness, Action::MoveLeft, Position::new(3, 1));
+        harness.tap(0, Action::PlaceBomb);
+        walk(&mut harness, Action::MoveRight, Position::new(4, 1));
+        walk(&mut harness, Action::MoveDown, pocket);
+        wait_for_explosion(&mut harness);
+        let level = harness.resource::<Level>();
+        assert_eq!(level.enemy_positions().len(), 1);
+        assert!(!level.ending_visible);
+
+        walk(&mut harness, Action::MoveUp, Position::new(4, 1));
+        walk(&mut harness, Action::MoveRight, Position::new(5, 1));
+        harness.tap(0, Action::PlaceBomb);
+        walk(&mut harness, Action::MoveLeft, Position::new(4, 1));
+        walk(&mut harness, Action::MoveDown, pocket);
+        wait_for_explosion(&mut harness);
+        let level = harness.resource::<Level>();
+        assert!(level.enemy_positions().is_empty());
+        assert!(level.ending_visible);
+        assert_eq!(player_position(&harness), Some(pocket));
+    }
+
+    #[test]
+    fn test_player_gone_loses_the_level() {
+        let mut harness = Harness::new(
+            "
+########
+#o  x e#
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        harness.tap(0, Action::PlaceBomb);
+        let lost = harness.run_until(400, |world| {
+            world.resource::<State<GameState>>().current() == &GameState::Lost
+        });
+        assert!(lost, "the game wasn't lost after the player died");
+        assert!(player_position(&harness).is_none());
+        let world = &mut harness.app.world;
+        assert!(world
+            .query::<&DespawnAfterAnimation>()
+            .iter(world)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn test_player_gone_doesnt_wait_for_the_animation() {
+        let mut harness = Harness::new(
+            "
+########
+#o  x e#
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        harness.tap(0, Action::PlaceBomb);
+        let died = harness.run_until(400, |world| {
+            world.resource::<Level>().player_positions.is_empty()
+        });
+        assert!(died, "the player never died");
+        // online, the animations of two instances don't end on the same tick
+        let lost = harness.run_until(60, |world| {
+            let players: Vec<_> = world
+                .query_filtered::<Entity, With<Player>>()
+                .iter(world)
+                .collect();
+            for player in players {
+                world.entity_mut(player).remove::<Animator<Transform>>();
+            }
+            world.resource::<State<GameState>>().current() == &GameState::Lost
+        });
+        assert!(lost, "the game waited for the dying animation");
+    }
+
+    fn hidden_walls(harness: &mut Harness) -> Vec<Position> {
+        let world = &mut harness.app.world;
+        world
+            .query::<&WallChunk>()
+            .iter(world)
+            .flat_map(|chunk| chunk.hidden.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_walls_in_front_of_the_player_are_see_through() {
+        let mut harness = Harness::new(
+            "
+|######|
+|o    e|
+|######|
+|x     |
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        walk(&mut harness, Action::MoveRight, Position::new(2, 1));
+        harness.tick();
+        let hidden = hidden_walls(&mut harness);
+        assert!(hidden.contains(&Position::new(2, 2)));
+        assert!(!hidden.contains(&Position::new(2, 0)));
+    }
+
+    #[test]
+    fn test_no_walls_are_see_through_from_above() {
+        let mut harness = Harness::new(
+            "
+########
+#o    e#
+########
+#x     #
+########
+",
+        );
+        freeze_enemies(&mut harness);
+        harness.app.world.resource_mut::<Settings>().top_down = true;
+        walk(&mut harness, Action::MoveRight, Position::new(2, 1));
+        harness.tick();
+        assert!(hidden_walls(&mut harness).is_empty());
+    }
+
+    fn particle_count(harness: &mut Harness) -> usize {
+        let world = &mut harness.app.world;
+        world.query::<&Particle>().iter(world).count()
+    }
+
+    #[test]
+    fn test_coins_sparkle_unless_particles_are_off() {
+        for (quality, sparkles) in [(ParticleQuality::High, true), (ParticleQuality::Off, false)] {
+            let mut harness = Harness::new(
+                "
+########
+#o *x e#
+########
+",
+            );
+            freeze_enemies(&mut harness);
+            harness.app.world.resource_mut::<Settings>().particles = quality;
+            walk(&mut harness, Action::MoveRight, Position::new(3, 1));
+            harness.tick();
+            assert_eq!(particle_count(&mut harness) > 0, sparkles, "{quality:?}");
+        }
+    }
+}
@@ -1,25 +1,72 @@
+mod camera;
+mod effects;
+mod geometry;
 mod level;
 mod logic;
+mod observation;
+mod occupancy;
+mod particles;
+mod resume;
+mod scoring;
+mod simulation;
 mod statics;
+mod theme;
 mod types;
 pub mod ui;
+mod versus;
 
 use bevy::prelude::*;
 
-use self::types::{GoNextLevelEvent, PlayerDiedEvent, ShowLevelExitEvent};
+use self::simulation::run_simulation;
+use self::types::{GoNextLevelEvent, PlayerDiedEvent, PlayerGoneEvent, ShowLevelExitEvent};
 
 use super::GameState;
 
-pub use statics::sizes;
-pub use types::{BlockType, CurrentLevel, Score};
+pub use effects::Effects;
+pub use geometry::explosion_cue_mesh;
+pub use level::Level;
+pub use logic::{
+    burn_fuse, enemy_direction, enemy_logic, explosion_ticks, move_entities, move_step,
+};
+pub use observation::{LevelView, Observation, Observer};
+pub use resume::{ResumeRun, SavedRun};
+pub use simulation::{GameSnapshot, Simulation};
+pub use statics::{
+    sizes, BOMB_RANGE, BOMB_SECONDS, CAMERA_VIEW, ENEMY_SPEED_EASY, EXPLOSION_SECONDS, FPS, LEVELS,
+    PLAYER_SPEED,
+};
+pub use types::{
+    BlockType, BoardDirection, CurrentLevel, CustomLevel, Enemy, GameMode, LevelCompletedEvent,
+    Location, Movement, Player, Position, PredictedInputs, RoundOverEvent, Score, Speed,
+    StartLevel, Unattended, Versus,
+};
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<ShowLevelExitEvent>()
+        let simulation = Simulation::new(&mut app.world);
+        let themes = theme::Themes::load();
+        app.insert_resource(simulation)
+            .init_resource::<PredictedInputs>()
+            .init_resource::<Effects>()
+            .init_resource::<Unattended>()
+            .init_resource::<CustomLevel>()
+            .insert_resource(theme::ActiveTheme(
+                themes.get(statics::DEFAULT_THEME).clone(),
+            ))
+            .insert_resource(themes)
+            .add_system(camera::flat_colours)
+            .add_system(theme::apply_theme)
+            .add_system_to_stage(CoreStage::PostUpdate, theme::outline_players)
+            .add_system_to_stage(CoreStage::PostUpdate, theme::explosion_shapes)
+            .add_event::<ShowLevelExitEvent>()
+            .add_event::<LevelCompletedEvent>()
             .add_event::<GoNextLevelEvent>()
             .add_event::<PlayerDiedEvent>()
+            .add_event::<PlayerGoneEvent>()
+            .add_event::<RoundOverEvent>()
+            .add_event::<particles::ParticleBurst>()
             .add_system_set(SystemSet::on_enter(GameState::Game).with_system(ui::setup_ui))
             .add_system_set(SystemSet::on_update(GameState::Game).with_system(logic::level_loading))
             .add_system_set(SystemSet::on_enter(GameState::Game).with_system(logic::first_level))
@@ -27,25 +74,35 @@ impl Plugin for GamePlugin {
                 SystemSet::on_exit(GameState::Running).with_system(logic::cleanup_level),
             )
             .add_system_set(SystemSet::on_exit(GameState::Running).with_system(ui::cleanup_ui))
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(ui::setup_pause))
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(ui::cleanup_pause))
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(ui::unpause_input)
+                    .with_system(resume::quit_run),
+            )
+            .add_system_to_stage(CoreStage::Last, resume::save_on_exit)
             .add_system_set(
                 SystemSet::on_update(GameState::Running)
-                    .with_system(logic::level_loading)
+                    .with_system(run_simulation.exclusive_system())
                     .with_system(logic::wobble)
                     .with_system(logic::wobble_enemy)
-                    .with_system(logic::keyboard_input_system)
-                    .with_system(logic::wall_visibility)
-                    .with_system(logic::update_level)
-                    .with_system(logic::tween_done_remove_handler)
-                    .with_system(logic::bomb_counter)
-                    .with_system(logic::bomb_explosion_destruction)
-                    .with_system(logic::enemy_logic)
-                    .with_system(logic::move_entities)
-                    .with_system(logic::show_level_exit)
-                    .with_system(logic::player_did_die_system)
+                    .with_system(geometry::wall_visibility)
+                    .with_system(camera::frame_level)
+                    .with_system(logic::play_effects)
+                    .with_system(particles::spawn_particles.after(logic::play_effects))
+                    .with_system(particles::update_particles)
+                    .with_system(logic::animation_done)
+                    .with_system(logic::player_gone_system.after(logic::animation_done))
+                    .with_system(resume::quit_run)
                     .with_system(logic::finish_level)
                     .with_system(ui::update_ui_bombs)
                     .with_system(ui::update_ui_level)
-                    .with_system(ui::update_ui_score),
+                    .with_system(ui::update_ui_score)
+                    .with_system(ui::show_tally)
+                    .with_system(ui::tally_input)
+                    .with_system(versus::show_round_result)
+                    .with_system(ui::pause_input),
             );
     }
 }
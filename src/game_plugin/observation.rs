@@ -0,0 +1,170 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::Serialize;
+
+use super::level::Level;
+use super::types::*;
+
+/// What can be seen of the running level from outside of the game. Positions
+/// are board tiles, `x` is the column and `z` the row
+#[derive(Debug, Serialize)]
+pub struct Observation {
+    pub players: Vec<PlayerView>,
+    pub enemies: Vec<Position>,
+    pub coins: Vec<Position>,
+    pub bombs: Vec<BombView>,
+    pub explosions: Vec<Position>,
+    /// Only set once the exit is open
+    pub exit: Option<Position>,
+    /// One score for all players, or one per player in versus
+    pub scores: Vec<Score>,
+    pub finished: bool,
+}
+
+/// The parts of a level that don't change while it is played
+#[derive(Debug, Serialize)]
+pub struct LevelView {
+    pub mode: GameMode,
+    /// The campaign level, or the arena in versus
+    pub level: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub round: Option<usize>,
+    /// See `Level::layout`
+    pub rows: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerView {
+    pub index: usize,
+    pub position: Position,
+    pub alive: bool,
+    pub exited: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BombView {
+    pub position: Position,
+    pub owner: usize,
+    /// Seconds until it explodes
+    pub fuse: f32,
+}
+
+#[derive(SystemParam)]
+pub struct Observer<'w, 's> {
+    level: Res<'w, Level>,
+    current: Res<'w, CurrentLevel>,
+    mode: Res<'w, GameMode>,
+    score: Res<'w, Score>,
+    versus: Res<'w, Versus>,
+    players: Query<'w, 's, (&'static Player, &'static Location)>,
+    bombs: Query<'w, 's, (&'static Bomb, &'static BombOwner, &'static Location)>,
+    explosions: Query<'w, 's, &'static Location, With<BombExplosion>>,
+}
+
+impl Observer<'_, '_> {
+    /// Whether another level was started since the system last ran. Starting
+    /// the same level again counts too: inserting the resources again marks
+    /// them changed, but never added
+    pub fn level_changed(&self) -> bool {
+        self.current.is_changed()
+    }
+
+    pub fn describe_level(&self) -> LevelView {
+        LevelView {
+            mode: *self.mode,
+            level: self.current.0,
+            round: (*self.mode == GameMode::Versus).then_some(self.versus.round),
+            rows: self.level.layout(),
+        }
+    }
+
+    pub fn observe(&self) -> Observation {
+        let level = &self.level;
+        let mut players: Vec<_> = self
+            .players
+            .iter()
+            .map(|(player, location)| PlayerView {
+                index: player.0,
+                position: location.0,
+                alive: level.player_positions.contains_key(&player.0),
+                exited: level.exited.contains(&player.0),
+            })
+            .collect();
+        players.sort_by_key(|player| player.index);
+        let mut bombs: Vec<_> = self
+            .bombs
+            .iter()
+            .map(|(bomb, owner, location)| BombView {
+                position: location.0,
+                owner: owner.0,
+                fuse: bomb.0,
+            })
+            .collect();
+        bombs.sort_by_key(|bomb| (bomb.position.z, bomb.position.x));
+        Observation {
+            players,
+            enemies: sorted(level.enemy_positions().values().copied()),
+            coins: sorted(level.coin_positions().values().copied()),
+            bombs,
+            explosions: sorted(self.explosions.iter().map(|location| location.0)),
+            exit: level.ending_position.filter(|_| level.ending_visible),
+            scores: match *self.mode {
+                GameMode::Versus => self.versus.scores.clone(),
+                _ => vec![self.score.clone()],
+            },
+            finished: level.finished,
+        }
+    }
+}
+
+/// Row by row, so that the same state always looks the same
+fn sorted(positions: impl Iterator<Item = Position>) -> Vec<Position> {
+    let mut positions: Vec<_> = positions.collect();
+    positions.sort_by_key(|position| (position.z, position.x));
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless_plugin::harness::Harness;
+    use crate::GameState;
+
+    #[derive(Default)]
+    struct LevelsSeen(usize);
+
+    fn count_levels(observer: Observer, mut seen: ResMut<LevelsSeen>) {
+        if observer.level_changed() {
+            seen.0 += 1;
+        }
+    }
+
+    #[test]
+    fn test_restarting_the_level_is_a_new_level() {
+        let mut harness = Harness::new(
+            "
+#######
+#o x e#
+#######
+",
+        );
+        harness
+            .app
+            .init_resource::<LevelsSeen>()
+            .add_system_set(SystemSet::on_update(GameState::Running).with_system(count_levels));
+        harness.tick();
+        harness.tick();
+        assert_eq!(harness.resource::<LevelsSeen>().0, 1);
+
+        // like trying again after losing it
+        harness
+            .app
+            .world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::Game)
+            .unwrap();
+        let restarted = harness.run_until(60, |world| world.resource::<LevelsSeen>().0 == 2);
+        assert!(restarted, "the same level started again unnoticed");
+        harness.tick();
+        assert_eq!(harness.resource::<LevelsSeen>().0, 2);
+    }
+}
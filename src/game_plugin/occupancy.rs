@@ -0,0 +1,80 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::types::Position;
+
+/// What takes up a tile besides the players and the walls
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Occupant {
+    Enemy,
+    Coin,
+    Bomb,
+}
+
+/// The entities on every tile of the level, so that collisions only have to
+/// look at a single tile instead of everything in the level
+#[derive(Debug, Clone, Default)]
+pub struct Occupancy {
+    /// Only the tiles that hold something, which keeps the snapshots of every
+    /// frame as small as the number of things in the level
+    tiles: HashMap<Position, Vec<(Occupant, Entity)>>,
+}
+
+impl Occupancy {
+    pub fn insert(&mut self, kind: Occupant, entity: Entity, position: Position) {
+        self.tiles.entry(position).or_default().push((kind, entity));
+    }
+
+    pub fn remove(&mut self, kind: Occupant, entity: Entity, position: Position) {
+        if let Some(tile) = self.tiles.get_mut(&position) {
+            tile.retain(|n| n != &(kind, entity));
+            if tile.is_empty() {
+                self.tiles.remove(&position);
+            }
+        }
+    }
+
+    /// The entities of the kind on the position
+    pub fn at(&self, kind: Occupant, position: Position) -> impl Iterator<Item = Entity> + '_ {
+        self.tiles
+            .get(&position)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter(move |(n, _)| *n == kind)
+            .map(|(_, entity)| *entity)
+    }
+
+    pub fn clear(&mut self, kind: Occupant) {
+        self.tiles.retain(|_, tile| {
+            tile.retain(|(n, _)| *n != kind);
+            !tile.is_empty()
+        });
+    }
+
+    /// Bombs get new entities when a rollback spawns them again
+    pub fn remap(&mut self, kind: Occupant, map: impl Fn(Entity) -> Entity) {
+        for (n, entity) in self.tiles.values_mut().flatten() {
+            if *n == kind {
+                *entity = map(*entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_occupied_tiles_are_kept() {
+        let mut occupancy = Occupancy::default();
+        let (coin, bomb) = (Entity::from_raw(1), Entity::from_raw(2));
+        occupancy.insert(Occupant::Coin, coin, Position::new(3, 4));
+        occupancy.insert(Occupant::Bomb, bomb, Position::new(3, 4));
+        occupancy.remove(Occupant::Coin, coin, Position::new(3, 4));
+        assert_eq!(occupancy.tiles.len(), 1);
+        occupancy.clear(Occupant::Bomb);
+        assert!(occupancy.tiles.is_empty());
+        assert_eq!(occupancy.at(Occupant::Bomb, Position::new(3, 4)).count(), 0);
+    }
+}
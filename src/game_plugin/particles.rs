@@ -0,0 +1,215 @@
+use bevy::prelude::*;
+
+use crate::{settings::Settings, MeshHandles};
+
+use super::theme::{ActiveTheme, Theme};
+use super::types::LevelItem;
+
+/// What a burst of particles looks like
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BurstKind {
+    /// Thrown around where a bomb went off
+    Debris,
+    /// Where a coin was picked up
+    Sparkle,
+    EnemyDeath,
+    /// The index of the player that died
+    PlayerDeath(usize),
+    ExitOpened,
+}
+
+/// Asks for particles at a place. The simulation queues them as an `Effect`,
+/// the particles themselves only exist for the eye and are never part of a snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticleBurst {
+    pub kind: BurstKind,
+    pub position: Vec3,
+}
+
+struct BurstStyle {
+    count: usize,
+    /// How fast the particles fly away from the burst, sideways and up
+    speed: f32,
+    up: f32,
+    gravity: f32,
+    lifetime: f32,
+}
+
+impl BurstKind {
+    fn style(&self) -> BurstStyle {
+        match self {
+            BurstKind::Debris => BurstStyle {
+                count: 24,
+                speed: 1.5,
+                up: 2.0,
+                gravity: 6.0,
+                lifetime: 0.8,
+            },
+            BurstKind::Sparkle => BurstStyle {
+                count: 8,
+                speed: 0.4,
+                up: 1.0,
+                gravity: 0.5,
+                lifetime: 0.6,
+            },
+            BurstKind::EnemyDeath | BurstKind::PlayerDeath(_) => BurstStyle {
+                count: 16,
+                speed: 1.0,
+                up: 1.5,
+                gravity: 4.0,
+                lifetime: 1.0,
+            },
+            BurstKind::ExitOpened => BurstStyle {
+                count: 20,
+                speed: 0.3,
+                up: 2.0,
+                gravity: -0.5,
+                lifetime: 1.5,
+            },
+        }
+    }
+
+    fn color(&self, theme: &Theme) -> (f32, f32, f32) {
+        match self {
+            BurstKind::Debris => theme.explosion,
+            BurstKind::Sparkle => theme.coin,
+            BurstKind::EnemyDeath => theme.enemy,
+            BurstKind::PlayerDeath(index) => {
+                theme.players.get(*index).copied().unwrap_or(theme.coin)
+            }
+            BurstKind::ExitOpened => (1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec3,
+    gravity: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+impl Particle {
+    /// Moves the particle on by `delta` seconds, false once it faded away
+    fn step(&mut self, transform: &mut Transform, delta: f32) -> bool {
+        self.age += delta;
+        self.velocity.y -= self.gravity * delta;
+        transform.translation += self.velocity * delta;
+        self.age < self.lifetime
+    }
+
+    /// How much of the particle is still visible
+    fn alpha(&self) -> f32 {
+        (1.0 - self.age / self.lifetime).clamp(0.0, 1.0)
+    }
+}
+
+/// xorshift, the particles only need to look random
+fn random(seed: &mut u32) -> f32 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 17;
+    *seed ^= *seed << 5;
+    (*seed % 10_000) as f32 / 10_000.0
+}
+
+/// Spawns the particles of every burst, as many as the quality setting allows.
+/// The particles of a burst share one material, which fades with them
+pub fn spawn_particles(
+    mut commands: Commands,
+    mut bursts: EventReader<ParticleBurst>,
+    settings: Res<Settings>,
+    active: Res<ActiveTheme>,
+    handles: Res<MeshHandles>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut seed: Local<u32>,
+) {
+    if *seed == 0 {
+        *seed = 0x2545_f491;
+    }
+    for burst in bursts.iter() {
+        let style = burst.kind.style();
+        let count = settings.particles.count(style.count);
+        if count == 0 {
+            continue;
+        }
+        let (r, g, b) = burst.kind.color(&active.0);
+        let material = materials.add(StandardMaterial {
+            base_color: Color::rgb(r, g, b),
+            emissive: Color::rgb(r, g, b) * 0.5,
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        });
+        for _ in 0..count {
+            let angle = random(&mut seed) * std::f32::consts::TAU;
+            let speed = style.speed * (0.5 + random(&mut seed));
+            let velocity = Vec3::new(
+                angle.cos() * speed,
+                style.up * (0.5 + random(&mut seed)),
+                angle.sin() * speed,
+            );
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: handles.particle.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_translation(burst.position),
+                    ..default()
+                })
+                .insert(Particle {
+                    velocity,
+                    gravity: style.gravity,
+                    age: 0.0,
+                    lifetime: style.lifetime * (0.75 + random(&mut seed) / 2.0),
+                })
+                .insert(LevelItem);
+        }
+    }
+}
+
+/// Moves and fades the particles, and removes them once they are gone
+pub fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &Handle<StandardMaterial>,
+    )>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut particle, mut transform, material) in particles.iter_mut() {
+        if !particle.step(&mut transform, delta) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        // the particles of a burst are about as old, the fade follows any of them
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color.set_a(particle.alpha());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_particles_fall_and_fade() {
+        let mut particle = Particle {
+            velocity: Vec3::new(1.0, 0.0, 0.0),
+            gravity: 10.0,
+            age: 0.0,
+            lifetime: 1.0,
+        };
+        let mut transform = Transform::default();
+        assert!(particle.step(&mut transform, 0.5));
+        assert!(transform.translation.x > 0.0);
+        assert!(particle.velocity.y < 0.0);
+        assert_eq!(particle.alpha(), 0.5);
+        assert!(!particle.step(&mut transform, 0.5));
+        assert_eq!(particle.alpha(), 0.0);
+    }
+}
@@ -0,0 +1,163 @@
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*, window::WindowCloseRequested};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::{save, settings::Action, GameState};
+
+use super::level::{Level, LevelState};
+use super::types::{Bomb, CurrentLevel, GameMode, Location, Movement, Player, Score, Unattended};
+
+const RUN_FILE: &str = "pacbomber_run.ron";
+
+/// A game that was quit in the middle of a level
+#[derive(Serialize, Deserialize)]
+pub struct SavedRun {
+    pub level: usize,
+    #[serde(default)]
+    pub mode: GameMode,
+    pub score: Score,
+    pub state: LevelState,
+}
+
+impl SavedRun {
+    pub fn exists() -> bool {
+        Path::new(RUN_FILE).exists()
+    }
+
+    /// A run can only be resumed once, so it is removed after loading
+    pub fn take() -> Option<Self> {
+        let run = save::read(RUN_FILE);
+        if let Err(e) = fs::remove_file(RUN_FILE) {
+            warn!("Could not remove {RUN_FILE}: {e}");
+        }
+        run
+    }
+
+    fn save(&self) {
+        save::write(RUN_FILE, self);
+    }
+}
+
+/// A run that should be continued instead of starting a new game
+pub struct ResumeRun(pub Option<SavedRun>);
+
+/// The run that is being played, as far as it is needed to resume it
+#[derive(SystemParam)]
+pub struct RunProgress<'w, 's> {
+    level: Option<Res<'w, Level>>,
+    mode: Res<'w, GameMode>,
+    current_level: Option<Res<'w, CurrentLevel>>,
+    score: Res<'w, Score>,
+    player: Query<'w, 's, &'static Location, (With<Player>, With<Movement>)>,
+    bombs: Query<'w, 's, &'static Bomb>,
+    unattended: Res<'w, Unattended>,
+}
+
+impl RunProgress<'_, '_> {
+    fn save(&self) {
+        // nothing was played yet
+        let (level, current_level) = match (&self.level, &self.current_level) {
+            (Some(level), Some(current_level)) => (level, current_level),
+            _ => return,
+        };
+        // a finished level, dead players or a versus match can't be resumed
+        if level.finished
+            || self.player.is_empty()
+            || *self.mode == GameMode::Versus
+            || self.unattended.0
+        {
+            return;
+        }
+        SavedRun {
+            level: current_level.0,
+            mode: *self.mode,
+            score: self.score.clone(),
+            state: level.state(|entity| self.bombs.get(entity).ok().map(|bomb| bomb.0)),
+        }
+        .save();
+    }
+}
+
+/// Stores the current run when the player quits to the menu (`Action::Back`),
+/// while playing or paused
+pub fn quit_run(
+    mut actions: ResMut<Input<Action>>,
+    mut app_state: ResMut<State<GameState>>,
+    progress: RunProgress,
+) {
+    if !actions.clear_just_pressed(Action::Back) {
+        return;
+    }
+    progress.save();
+    // leaves the pause as well
+    app_state.replace(GameState::Menu).unwrap();
+}
+
+/// Stores the current run when the window is closed or the app exits. Runs in
+/// every state, but only a level that is played or paused is stored
+pub fn save_on_exit(
+    mut close_requests: EventReader<WindowCloseRequested>,
+    mut exits: EventReader<AppExit>,
+    app_state: Res<State<GameState>>,
+    progress: RunProgress,
+) {
+    let closing = close_requests.iter().count() > 0;
+    let exiting = exits.iter().count() > 0;
+    if !closing && !exiting {
+        return;
+    }
+    if matches!(app_state.current(), GameState::Running | GameState::Paused) {
+        progress.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless_plugin::{harness::Harness, tick, HeadlessPlugin};
+    use crate::settings::Settings;
+
+    fn state(harness: &Harness) -> GameState {
+        *harness.resource::<State<GameState>>().current()
+    }
+
+    #[test]
+    fn test_back_while_paused_goes_to_the_menu() {
+        let mut harness = Harness::new(
+            "
+#######
+#o x e#
+#######
+",
+        );
+        harness.tap(0, Action::Pause);
+        harness.tick();
+        assert_eq!(state(&harness), GameState::Paused);
+        harness.tap(0, Action::Back);
+        let left = harness.run_until(10, |world| {
+            world.resource::<State<GameState>>().current() == &GameState::Menu
+        });
+        assert!(left, "Back didn't leave the paused level");
+        // the level underneath the pause is left as well
+        let app_state = harness.resource::<State<GameState>>();
+        assert!(app_state.inactives().is_empty());
+    }
+
+    #[test]
+    fn test_menu_without_a_level() {
+        let mut app = crate::app(Some(HeadlessPlugin {
+            mode: GameMode::Campaign,
+            level: 0,
+            stdio_agent: false,
+        }));
+        // like a windowed start, where nothing loaded a level yet
+        app.insert_resource(State::new(GameState::Menu))
+            .insert_resource(Settings::default());
+        tick(&mut app);
+        assert!(app.world.get_resource::<CurrentLevel>().is_none());
+        assert_eq!(
+            app.world.resource::<State<GameState>>().current(),
+            &GameState::Menu
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::level::Level;
+use super::types::Score;
+
+pub const POINTS_PER_COIN: usize = 10;
+pub const POINTS_PER_KILL: usize = 100;
+pub const POINTS_PER_UNUSED_BOMB: usize = 50;
+pub const POINTS_PER_SAVED_SECOND: usize = 10;
+pub const POINTS_PER_SAVED_MOVE: usize = 5;
+
+/// Finishing a level within this many seconds awards a time bonus
+pub const TIME_PAR_SECONDS: f32 = 90.0;
+
+/// Everything that happened in the current level that counts towards the tally
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LevelStats {
+    pub coins: usize,
+    pub kills: usize,
+    pub kill_points: usize,
+    pub best_combo: usize,
+    pub moves: usize,
+    pub bombs_used: usize,
+    /// Coins that were still on the board when the exit opened
+    pub coins_missed: usize,
+    pub time: f32,
+    /// Number of kills per bomb, so that multi-kills can be rewarded
+    #[serde(skip)]
+    combos: HashMap<Entity, usize>,
+}
+
+impl LevelStats {
+    /// Records a collected coin and returns the points for it
+    pub fn record_coin(&mut self) -> usize {
+        self.coins += 1;
+        POINTS_PER_COIN
+    }
+
+    /// Records an enemy killed by `bomb` and returns the points for it.
+    /// Every additional kill by the same bomb is worth more than the previous one
+    pub fn record_kill(&mut self, bomb: Entity) -> usize {
+        let combo = self.combos.entry(bomb).or_insert(0);
+        *combo += 1;
+        let points = POINTS_PER_KILL * *combo;
+        self.best_combo = self.best_combo.max(*combo);
+        self.kills += 1;
+        self.kill_points += points;
+        points
+    }
+
+    /// Moves the kills of a bomb over to its new entity, after it was spawned again
+    pub fn remap_bomb(&mut self, old: Entity, new: Entity) {
+        if let Some(combo) = self.combos.remove(&old) {
+            self.combos.insert(new, combo);
+        }
+    }
+}
+
+/// Optional goals of a level, each one reached is worth a star
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Goal {
+    /// Collect all coins before the exit opens
+    AllCoins,
+    MaxMoves(usize),
+    MaxBombs(usize),
+}
+
+impl Goal {
+    pub fn is_met(&self, stats: &LevelStats) -> bool {
+        match self {
+            Goal::AllCoins => stats.coins_missed == 0,
+            Goal::MaxMoves(n) => stats.moves <= *n,
+            Goal::MaxBombs(n) => stats.bombs_used <= *n,
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Goal::AllCoins => "Collect all coins before the exit opens".to_string(),
+            Goal::MaxMoves(n) => format!("Finish in at most {n} moves"),
+            Goal::MaxBombs(n) => format!("Use at most {n} bombs"),
+        }
+    }
+}
+
+/// The breakdown of all points of a finished level
+#[derive(Debug, Default)]
+pub struct Tally {
+    pub coins: usize,
+    pub kills: usize,
+    pub time: usize,
+    pub bombs: usize,
+    pub moves: usize,
+    pub goals: Vec<(Goal, bool)>,
+}
+
+impl Tally {
+    /// Coins and kills are awarded while playing, the bonuses only once the level is done
+    pub fn new(level: &Level, goals: &[Goal], score: &Score) -> Self {
+        let stats = &level.stats;
+        let saved_seconds = (TIME_PAR_SECONDS - stats.time).max(0.0) as usize;
+        let saved_moves = level.free_tiles().saturating_sub(stats.moves);
+        Tally {
+            coins: stats.coins * POINTS_PER_COIN,
+            kills: stats.kill_points,
+            time: saved_seconds * POINTS_PER_SAVED_SECOND,
+            bombs: score.bombs * POINTS_PER_UNUSED_BOMB,
+            moves: saved_moves * POINTS_PER_SAVED_MOVE,
+            goals: goals.iter().map(|g| (*g, g.is_met(stats))).collect(),
+        }
+    }
+
+    pub fn stars(&self) -> usize {
+        self.goals.iter().filter(|(_, met)| *met).count()
+    }
+
+    pub fn bonus(&self) -> usize {
+        self.time + self.bombs + self.moves
+    }
+
+    pub fn total(&self) -> usize {
+        self.coins + self.kills + self.bonus()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kill_combos() {
+        let mut stats = LevelStats::default();
+        let (a, b) = (Entity::from_raw(1), Entity::from_raw(2));
+        assert_eq!(stats.record_kill(a), POINTS_PER_KILL);
+        assert_eq!(stats.record_kill(a), POINTS_PER_KILL * 2);
+        assert_eq!(stats.record_kill(b), POINTS_PER_KILL);
+        assert_eq!(stats.kills, 3);
+        assert_eq!(stats.best_combo, 2);
+        assert_eq!(stats.kill_points, POINTS_PER_KILL * 4);
+    }
+
+    #[test]
+    fn test_goals() {
+        let stats = LevelStats {
+            moves: 30,
+            bombs_used: 2,
+            coins_missed: 1,
+            ..Default::default()
+        };
+        assert!(!Goal::AllCoins.is_met(&stats));
+        assert!(Goal::MaxMoves(30).is_met(&stats));
+        assert!(!Goal::MaxBombs(1).is_met(&stats));
+    }
+}
@@ -0,0 +1,283 @@
+use bevy::{ecs::system::BoxedSystem, prelude::*, utils::HashMap};
+use bevy_tweening::Animator;
+
+use crate::{net_plugin::NetSession, MaterialHandles};
+
+use super::level::Level;
+use super::logic::{self, add_bomb, add_bomb_explosion, insert_bomb_explosion_tween};
+use super::types::*;
+use super::versus;
+
+/// The systems that advance the game by one tick. They run in their own stage,
+/// so that an online match can go back and step through several ticks in one frame
+pub struct Simulation {
+    stage: SystemStage,
+    save: BoxedSystem<(), GameSnapshot>,
+    restore: BoxedSystem<GameSnapshot, ()>,
+}
+
+impl Simulation {
+    pub fn new(world: &mut World) -> Self {
+        // the order of the systems has to be the same on every instance
+        let stage = SystemStage::single_threaded()
+            .with_system(logic::level_loading)
+            .with_system(versus::round_input.after(logic::level_loading))
+            .with_system(logic::keyboard_input_system.after(versus::round_input))
+            .with_system(logic::enemy_logic.after(logic::keyboard_input_system))
+            .with_system(logic::move_entities.after(logic::enemy_logic))
+            .with_system(logic::update_level.after(logic::move_entities))
+            .with_system(logic::bomb_counter.after(logic::update_level))
+            .with_system(logic::explosion_timer.after(logic::bomb_counter))
+            .with_system(logic::bomb_explosion_destruction.after(logic::explosion_timer))
+            .with_system(logic::show_level_exit.after(logic::bomb_explosion_destruction))
+            .with_system(logic::player_did_die_system.after(logic::show_level_exit))
+            .with_system(logic::count_endings.after(logic::player_did_die_system))
+            .with_system(logic::level_timer.after(logic::count_endings));
+        let mut save: BoxedSystem<(), GameSnapshot> = Box::new(IntoSystem::into_system(save));
+        save.initialize(world);
+        let mut restore: BoxedSystem<GameSnapshot, ()> = Box::new(IntoSystem::into_system(restore));
+        restore.initialize(world);
+        Self {
+            stage,
+            save,
+            restore,
+        }
+    }
+
+    pub fn step(&mut self, world: &mut World) {
+        self.stage.run(world);
+    }
+
+    pub fn save(&mut self, world: &mut World) -> GameSnapshot {
+        self.save.run((), world)
+    }
+
+    pub fn load(&mut self, world: &mut World, snapshot: &GameSnapshot) {
+        self.restore.run(snapshot.clone(), world);
+        self.restore.apply_buffers(world);
+    }
+
+    /// Once the round is decided, nothing may be guessed anymore
+    pub fn can_predict(&self, world: &World) -> bool {
+        world
+            .get_resource::<Level>()
+            .is_none_or(|level| !level.finished)
+    }
+}
+
+/// Advances the game by one tick every frame, unless an online match steps it
+/// on its own or waits for the inputs of the other instance
+pub fn run_simulation(world: &mut World) {
+    if world
+        .get_resource::<NetSession>()
+        .is_some_and(NetSession::holds_simulation)
+    {
+        return;
+    }
+    world.resource_scope(|world, mut simulation: Mut<Simulation>| simulation.step(world));
+}
+
+/// Everything the simulation changes from one tick to the next
+#[derive(Clone)]
+pub struct GameSnapshot {
+    level: Level,
+    score: Score,
+    versus: Versus,
+    actors: Vec<ActorSnapshot>,
+    bombs: Vec<BombSnapshot>,
+    explosions: Vec<ExplosionSnapshot>,
+}
+
+#[derive(Clone)]
+struct ActorSnapshot {
+    entity: Entity,
+    location: Position,
+    translation: Vec3,
+    /// Movement and speed, only set while alive
+    movement: Option<(Movement, f32)>,
+    turn: Option<TurnBuffer>,
+    enemy: bool,
+}
+
+#[derive(Clone)]
+struct BombSnapshot {
+    entity: Entity,
+    fuse: f32,
+    owner: usize,
+    location: Position,
+    translation: Vec3,
+}
+
+#[derive(Clone)]
+struct ExplosionSnapshot {
+    bomb: Entity,
+    owner: usize,
+    ticks: u32,
+    location: Position,
+    translation: Vec3,
+}
+
+#[allow(clippy::type_complexity)]
+fn save(
+    level: Res<Level>,
+    score: Res<Score>,
+    versus: Res<Versus>,
+    actors: Query<
+        (
+            Entity,
+            &Location,
+            &Transform,
+            Option<&Movement>,
+            Option<&Speed>,
+            Option<&TurnBuffer>,
+            Option<&Enemy>,
+        ),
+        With<Actor>,
+    >,
+    bombs: Query<(Entity, &Bomb, &BombOwner, &Location, &Transform)>,
+    explosions: Query<(&BombExplosion, &Location, &Transform)>,
+) -> GameSnapshot {
+    GameSnapshot {
+        level: level.clone(),
+        score: score.clone(),
+        versus: versus.clone(),
+        actors: actors
+            .iter()
+            .map(
+                |(entity, location, transform, movement, speed, turn, enemy)| ActorSnapshot {
+                    entity,
+                    location: location.0,
+                    translation: transform.translation,
+                    movement: movement
+                        .zip(speed)
+                        .map(|(movement, speed)| (movement.clone(), speed.0)),
+                    turn: turn.cloned(),
+                    enemy: enemy.is_some(),
+                },
+            )
+            .collect(),
+        bombs: bombs
+            .iter()
+            .map(|(entity, bomb, owner, location, transform)| BombSnapshot {
+                entity,
+                fuse: bomb.0,
+                owner: owner.0,
+                location: location.0,
+                translation: transform.translation,
+            })
+            .collect(),
+        explosions: explosions
+            .iter()
+            .map(|(explosion, location, transform)| ExplosionSnapshot {
+                bomb: explosion.bomb,
+                owner: explosion.owner,
+                ticks: explosion.ticks,
+                location: location.0,
+                translation: transform.translation,
+            })
+            .collect(),
+    }
+}
+
+/// Players, enemies and coins keep their entities and are brought back to life
+/// if needed. Bombs and explosions are spawned again
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn restore(
+    In(snapshot): In<GameSnapshot>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    materials: Res<MaterialHandles>,
+    mut level: ResMut<Level>,
+    mut score: ResMut<Score>,
+    mut versus: ResMut<Versus>,
+    mut actors: Query<(&mut Location, &mut Transform, Option<&Movement>), With<Actor>>,
+    mut coins: Query<&mut Transform, (With<Coin>, Without<Actor>)>,
+    blasts: Query<Entity, Or<(With<Bomb>, With<BombExplosion>)>>,
+) {
+    for actor in snapshot.actors {
+        let (mut location, mut transform, movement) = match actors.get_mut(actor.entity) {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        if location.0 != actor.location {
+            location.0 = actor.location;
+        }
+        let mut entity = commands.entity(actor.entity);
+        match actor.movement {
+            Some((movement_then, speed)) => {
+                if movement.is_none() {
+                    // stop the dying animation
+                    entity
+                        .remove::<Animator<Transform>>()
+                        .remove::<DespawnAfterAnimation>();
+                    transform.rotation = Quat::IDENTITY;
+                    transform.scale = Vec3::ONE;
+                    if actor.enemy {
+                        entity.insert(Enemy);
+                    }
+                }
+                transform.translation = actor.translation;
+                entity.insert(movement_then).insert(Speed(speed));
+            }
+            None if movement.is_some() => {
+                transform.scale = Vec3::ZERO;
+                entity
+                    .remove::<Movement>()
+                    .remove::<Speed>()
+                    .remove::<Enemy>();
+            }
+            None => {}
+        }
+        if let Some(turn) = actor.turn {
+            entity.insert(turn);
+        }
+    }
+    for entity in snapshot.level.coin_positions().keys() {
+        if level.coin_positions().contains_key(entity) {
+            continue;
+        }
+        if let Ok(mut transform) = coins.get_mut(*entity) {
+            commands.entity(*entity).remove::<Animator<Transform>>();
+            transform.scale = Vec3::ONE;
+        }
+    }
+
+    for entity in blasts.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    *level = snapshot.level;
+    *score = snapshot.score;
+    *versus = snapshot.versus;
+    let mut respawned = HashMap::default();
+    for bomb in snapshot.bombs {
+        let id = add_bomb(
+            &mut commands,
+            &mut meshes,
+            &materials,
+            bomb.location,
+            bomb.translation,
+            bomb.owner,
+        );
+        commands.entity(id).insert(Bomb(bomb.fuse));
+        respawned.insert(bomb.entity, id);
+        level.stats.remap_bomb(bomb.entity, id);
+    }
+    level.remap_bombs(&respawned);
+    for explosion in snapshot.explosions {
+        let id = add_bomb_explosion(
+            &mut commands,
+            &mut meshes,
+            &materials,
+            respawned
+                .get(&explosion.bomb)
+                .copied()
+                .unwrap_or(explosion.bomb),
+            explosion.owner,
+            explosion.ticks,
+            explosion.location,
+            explosion.translation,
+        );
+        insert_bomb_explosion_tween(&mut commands, id, 0.0);
+    }
+}
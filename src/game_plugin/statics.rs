@@ -10,16 +10,84 @@ pub mod sizes {
     pub const bomb_size: f32 = 0.15;
 }
 
+use bevy::prelude::Vec3;
+
+use super::scoring::Goal;
+
 pub const FPS: f32 = 60.0;
 
-pub const LEVEL_COMPLETED_PAYLOAD: u64 = 42;
-pub const USER_DIED_PAYLOAD: u64 = 43;
+/// The direction the camera looks at the level from
+pub const CAMERA_VIEW: Vec3 = Vec3::new(0.0, 5.5, 3.0);
+/// Space around the level in the view of the camera
+pub const CAMERA_MARGIN: f32 = 0.3;
+pub const MIN_CAMERA_DISTANCE: f32 = 3.0;
+/// Levels that don't fit from here on aren't shown whole, the camera follows
+/// the players instead
+pub const MAX_CAMERA_DISTANCE: f32 = 12.0;
+/// The top-down camera only has to be above everything
+pub const TOP_DOWN_HEIGHT: f32 = 10.0;
+/// The most ground the top-down camera shows along each side, larger levels
+/// are followed like in the angled view
+pub const MAX_TOP_DOWN_VIEW: f32 = 12.0;
+/// How fast the camera catches up with a new framing
+pub const CAMERA_SPEED: f32 = 3.0;
+
+/// The tiles along each side of a chunk, which shares the meshes of its floor
+/// and walls
+pub const CHUNK_TILES: usize = 8;
+
+/// The completion event of animations of entities with a `DespawnAfterAnimation`
+pub const ANIMATION_DONE: u64 = 1;
 
 pub const PLAYER_SPEED: f32 = 0.25;
 pub const ENEMY_SPEED_EASY: f32 = 0.5;
+/// Seconds until a placed bomb explodes
+pub const BOMB_SECONDS: f32 = 1.5;
+/// A bomb reaches one tile less than its range in every direction
+pub const BOMB_RANGE: usize = 3;
+/// How long an explosion tile stays after its delay
+pub const EXPLOSION_SECONDS: f32 = 0.5;
+/// How long the result of a versus round is shown at least
+pub const ROUND_OVER_SECONDS: f32 = 1.0;
+/// How long a dying player takes to disappear
+pub const PLAYER_GONE_SECONDS: f32 = 0.4;
+/// How long the last player takes to sink into the exit
+pub const ENTER_EXIT_SECONDS: f32 = 0.7;
+/// The rounds a player has to win to take a versus match
+pub const MATCH_WINS: usize = 3;
+
+/// A level that comes with the game
+pub struct LevelData {
+    pub map: &'static str,
+    /// The name of the theme in `assets/themes.ron`
+    pub theme: &'static str,
+}
+
+const fn level(map: &'static str, theme: &'static str) -> LevelData {
+    LevelData { map, theme }
+}
 
-pub const LEVELS: &[&str] = &[L1, L2, L3, L4, L5];
+/// Campaign levels have a spawn for the second player (`O`), which is only used in co-op
+pub const LEVELS: &[LevelData] = &[
+    level(L1, "classic"),
+    level(L2, "classic"),
+    level(L3, "forest"),
+    level(L4, "ice"),
+    level(L5, "lava"),
+];
+/// Versus levels, they need a spawn for both players (`o` and `O`) but no exit
+pub const ARENAS: &[LevelData] = &[level(A1, "classic"), level(A2, "lava")];
+pub const ARENA_BOMBS: usize = 8;
 pub const LEVEL_BOMBS: &[usize] = &[3, 3, 3, 5, 5];
+/// For levels that don't come with the game
+pub const DEFAULT_THEME: &str = "classic";
+pub const LEVEL_GOALS: &[[Goal; 3]] = &[
+    [Goal::AllCoins, Goal::MaxMoves(60), Goal::MaxBombs(1)],
+    [Goal::AllCoins, Goal::MaxMoves(110), Goal::MaxBombs(1)],
+    [Goal::AllCoins, Goal::MaxMoves(100), Goal::MaxBombs(2)],
+    [Goal::AllCoins, Goal::MaxMoves(120), Goal::MaxBombs(3)],
+    [Goal::AllCoins, Goal::MaxMoves(130), Goal::MaxBombs(3)],
+];
 
 const L1: &str = r#"
 #################
@@ -29,7 +97,7 @@ const L1: &str = r#"
 #  #  |  #   |  #
 #  *  *  *   |  #
 #          e    #
-|  o            |
+|  oO           |
 #################
 "#;
 
@@ -40,7 +108,7 @@ const L2: &str = r#"
 | * *     *|*   * * |
 #---##-  ##### #### #
 |  *  *  * e # * *  |
-|          o        |
+|          oO       |
 #  -##-  ##### ---###
 | * *|  *  *  *  * x|
 #####################
@@ -51,7 +119,7 @@ const L3: &str = r#"
 | x*  *  * | *  *  *x |
 #--- ----- # ------ --#
 #  *  *  *   *  *  *  #
-#   ##   # o #   ##   #
+#   ##   # oO#   ##   #
 |  *| *  #   #  * |*  |
 |  *| *  *   *  * |*  |
 |  *| *  * e *  * |*  |
@@ -66,13 +134,13 @@ const L4: &str = r#"
 #--- ----- # -----# # #
 |  *  *  # e #  * |***|
 ##  ------ # -----| # #
-| x*| *  * o *  * |***|
+| x*| *  * oO*  * |***|
 #---##-----------##---#
 "#;
 
 pub const L5: &str = r#"
 #-----------------------#
-|x* * * * * o * * * * *x|
+|x* * * * * oO* * * * *x|
 | ##---- #-----# ----## |
 | #* *   #  x  #   * *# |
 | #----  # # # #  ----# |
@@ -81,3 +149,27 @@ pub const L5: &str = r#"
 |  *|*             *|*  |
 #-----------------------#
 "#;
+
+const A1: &str = r#"
+#################
+|o  *   *   *   |
+#  #  #---#  #  #
+|  *  |   |  *  |
+#  #    *    #  #
+|  *  |   |  *  |
+#  #  #---#  #  #
+|   *   *   *  O|
+#################
+"#;
+
+const A2: &str = r#"
+#####################
+|o   *    |    *    |
+#  ##-##  |  ##-##  #
+|  *   *     *   *  |
+#--#  ##  -  ##  #--#
+|  *   *     *   *  |
+#  ##-##  |  ##-##  #
+|    *    |    *   O|
+#####################
+"#;
@@ -0,0 +1,385 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_mod_outline::Outline;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{fs, path::PathBuf};
+
+use crate::{
+    settings::{Palette, Settings},
+    GameState, MaterialHandles, MeshHandles, SceneLight,
+};
+
+use super::level::Level;
+use super::statics::DEFAULT_THEME;
+use super::types::{BombExplosion, Player};
+
+const THEMES_FILE: &str = "assets/themes.ron";
+const PALETTES_FILE: &str = "assets/palettes.ron";
+/// The copies that are built into the game, for when the files can't be read
+const THEMES: &str = include_str!("../../assets/themes.ron");
+const PALETTES: &str = include_str!("../../assets/palettes.ron");
+
+type Rgb = (f32, f32, f32);
+
+fn color((r, g, b): Rgb) -> Color {
+    Color::rgb(r, g, b)
+}
+
+/// The colours a level is shown in
+#[derive(Debug, Clone, Deserialize)]
+pub struct Theme {
+    pub wall: Rgb,
+    pub floor_fg: Rgb,
+    pub floor_bg: Rgb,
+    pub ground: Rgb,
+    pub coin: Rgb,
+    pub enemy: Rgb,
+    /// One per player index
+    pub players: Vec<Rgb>,
+    pub outlines: Vec<Rgb>,
+    pub bomb: Rgb,
+    pub explosion: Rgb,
+    /// The background around the level
+    pub clear: Rgb,
+    /// Scales the intensity of the lights
+    pub light: f32,
+}
+
+/// The colours of a theme that a palette replaces
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PaletteColors {
+    pub wall: Option<Rgb>,
+    pub floor_fg: Option<Rgb>,
+    pub floor_bg: Option<Rgb>,
+    pub ground: Option<Rgb>,
+    pub coin: Option<Rgb>,
+    pub enemy: Option<Rgb>,
+    pub players: Option<Vec<Rgb>>,
+    pub outlines: Option<Vec<Rgb>>,
+    pub bomb: Option<Rgb>,
+    pub explosion: Option<Rgb>,
+    pub clear: Option<Rgb>,
+    pub light: Option<f32>,
+}
+
+/// All themes and palettes, by their name
+pub struct Themes {
+    pub themes: HashMap<String, Theme>,
+    pub palettes: HashMap<String, PaletteColors>,
+}
+
+/// The theme the level is shown in, with the palette applied
+pub struct ActiveTheme(pub Theme);
+
+impl Themes {
+    /// Reads the files in `assets`, so that themes can be changed without
+    /// building the game again
+    pub fn load() -> Self {
+        let mut themes: HashMap<String, Theme> = read_or_builtin(THEMES_FILE, THEMES);
+        if !themes.contains_key(DEFAULT_THEME) {
+            warn!("{THEMES_FILE} has no {DEFAULT_THEME} theme, using the built-in themes");
+            themes = builtin(THEMES_FILE, THEMES);
+        }
+        Themes {
+            themes,
+            palettes: read_or_builtin(PALETTES_FILE, PALETTES),
+        }
+    }
+
+    /// Unknown names fall back to the default theme
+    pub fn get(&self, name: &str) -> &Theme {
+        self.themes
+            .get(name)
+            .or_else(|| self.themes.get(DEFAULT_THEME))
+            .expect("no default theme")
+    }
+
+    /// The theme with the colours of the palette in the settings
+    pub fn with_palette(&self, name: &str, settings: &Settings) -> Theme {
+        let theme = self.get(name).clone();
+        match settings.palette.name().and_then(|n| self.palettes.get(n)) {
+            Some(palette) => theme.with(palette),
+            None => theme,
+        }
+    }
+}
+
+/// Relative to the same directory the asset server loads from, so the files
+/// are found no matter where the game was started from
+#[cfg(not(target_arch = "wasm32"))]
+fn asset_path(path: &str) -> PathBuf {
+    bevy::asset::FileAssetIo::get_base_path().join(path)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn asset_path(path: &str) -> PathBuf {
+    PathBuf::from(path)
+}
+
+/// The built-in copy is used when the file is missing or broken
+fn read_or_builtin<T: DeserializeOwned>(path: &str, data: &str) -> T {
+    let read = match fs::read_to_string(asset_path(path)) {
+        Ok(n) => ron::from_str(&n),
+        Err(e) => {
+            warn!("Could not read {path}, using the built-in one: {e}");
+            return builtin(path, data);
+        }
+    };
+    match read {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Broken {path}, using the built-in one: {e}");
+            builtin(path, data)
+        }
+    }
+}
+
+/// The built-in copies come with the game, broken data is a bug
+fn builtin<T: DeserializeOwned>(path: &str, data: &str) -> T {
+    ron::from_str(data).unwrap_or_else(|e| panic!("invalid built-in {path}: {e}"))
+}
+
+impl Theme {
+    fn with(self, palette: &PaletteColors) -> Theme {
+        let p = palette.clone();
+        Theme {
+            wall: p.wall.unwrap_or(self.wall),
+            floor_fg: p.floor_fg.unwrap_or(self.floor_fg),
+            floor_bg: p.floor_bg.unwrap_or(self.floor_bg),
+            ground: p.ground.unwrap_or(self.ground),
+            coin: p.coin.unwrap_or(self.coin),
+            enemy: p.enemy.unwrap_or(self.enemy),
+            players: p.players.unwrap_or(self.players),
+            outlines: p.outlines.unwrap_or(self.outlines),
+            bomb: p.bomb.unwrap_or(self.bomb),
+            explosion: p.explosion.unwrap_or(self.explosion),
+            clear: p.clear.unwrap_or(self.clear),
+            light: p.light.unwrap_or(self.light),
+        }
+    }
+
+    /// The colour of every material the theme changes
+    fn colors<'a>(
+        &self,
+        handles: &'a MaterialHandles,
+    ) -> Vec<(&'a Handle<StandardMaterial>, Color)> {
+        let mut colors = vec![
+            (&handles.wall_normal, color(self.wall)),
+            (&handles.wall_hidden, *color(self.wall).set_a(0.3)),
+            (&handles.floor_fg, color(self.floor_fg)),
+            (&handles.floor_bg, color(self.floor_bg)),
+            (&handles.ground, color(self.ground)),
+            (&handles.coin, color(self.coin)),
+            (&handles.enemy, color(self.enemy)),
+            (&handles.bomb, color(self.bomb)),
+            (&handles.explosion, color(self.explosion)),
+        ];
+        colors.extend(
+            handles
+                .players
+                .iter()
+                .zip(&self.players)
+                .map(|(handle, rgb)| (handle, color(*rgb))),
+        );
+        colors
+    }
+}
+
+/// Recolours the materials, the background and the lights when a level with
+/// another theme is loaded, or another palette was chosen. The menus always
+/// use the default theme, whatever level was played last
+#[allow(clippy::too_many_arguments)]
+pub fn apply_theme(
+    level: Option<Res<Level>>,
+    state: Res<State<GameState>>,
+    settings: Res<Settings>,
+    themes: Res<Themes>,
+    handles: Res<MaterialHandles>,
+    mut applied: Local<Option<(&'static str, Palette)>>,
+    mut active: ResMut<ActiveTheme>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    clear_color: Option<ResMut<ClearColor>>,
+    mut lights: Query<(&SceneLight, Option<&mut PointLight>, Option<&mut SpotLight>)>,
+) {
+    let in_menus = matches!(
+        state.current(),
+        GameState::Menu
+            | GameState::Levels
+            | GameState::Settings
+            | GameState::Connecting
+            | GameState::Loading
+    );
+    let name = match level {
+        Some(level) if !in_menus => level.theme,
+        _ => DEFAULT_THEME,
+    };
+    if *applied == Some((name, settings.palette)) {
+        return;
+    }
+    *applied = Some((name, settings.palette));
+    active.0 = themes.with_palette(name, &settings);
+    let theme = &active.0;
+    for (handle, base_color) in theme.colors(&handles) {
+        if let Some(material) = materials.get_mut(handle) {
+            material.base_color = base_color;
+        }
+    }
+    // the explosion glows in its own colour
+    if let Some(material) = materials.get_mut(&handles.explosion) {
+        material.emissive = color(theme.explosion);
+    }
+    if let Some(mut clear_color) = clear_color {
+        clear_color.0 = color(theme.clear);
+    }
+    for (light, point, spot) in lights.iter_mut() {
+        if let Some(mut point) = point {
+            point.intensity = light.intensity * theme.light;
+        }
+        if let Some(mut spot) = spot {
+            spot.intensity = light.intensity * theme.light;
+        }
+    }
+}
+
+/// Outlines new players, and all of them when the theme changed
+pub fn outline_players(
+    active: Res<ActiveTheme>,
+    mut players: Query<(&Player, &mut Outline)>,
+    added: Query<(), Added<Player>>,
+) {
+    if !active.is_changed() && added.is_empty() {
+        return;
+    }
+    for (player, mut outline) in players.iter_mut() {
+        if let Some(rgb) = active.0.outlines.get(player.0) {
+            outline.colour = color(*rgb);
+        }
+    }
+}
+
+/// With shape cues, explosions are crosses and can't be mistaken for coins
+/// of a similar colour
+pub fn explosion_shapes(
+    settings: Res<Settings>,
+    handles: Res<MeshHandles>,
+    mut explosions: Query<&mut Handle<Mesh>, Added<BombExplosion>>,
+) {
+    if !settings.shape_cues {
+        return;
+    }
+    for mut mesh in explosions.iter_mut() {
+        *mesh = handles.explosion_cue.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_plugin::statics::{ARENAS, LEVELS};
+    use crate::headless_plugin::harness::Harness;
+    use crate::settings::{Action, PLAYERS};
+
+    #[test]
+    fn test_every_level_has_a_theme() {
+        let themes = Themes::load();
+        let names = LEVELS.iter().chain(ARENAS).map(|level| level.theme);
+        for name in names.chain([DEFAULT_THEME]) {
+            let theme = themes.themes.get(name).unwrap_or_else(|| panic!("{name}"));
+            assert_eq!(theme.players.len(), PLAYERS, "{name}");
+            assert_eq!(theme.outlines.len(), PLAYERS, "{name}");
+        }
+        assert_eq!(themes.get("missing").wall, themes.get(DEFAULT_THEME).wall);
+    }
+
+    #[test]
+    fn test_themes_fall_back_to_the_built_in_ones() {
+        let read: HashMap<String, Theme> = read_or_builtin(THEMES_FILE, THEMES);
+        let missing: HashMap<String, Theme> = read_or_builtin("assets/missing.ron", THEMES);
+        // the file in `assets` is the one that was built in
+        assert_eq!(missing.len(), read.len());
+        assert!(missing.contains_key("lava"));
+    }
+
+    #[test]
+    fn test_files_are_found_next_to_the_assets() {
+        assert!(asset_path(THEMES_FILE).is_file());
+        assert!(asset_path(PALETTES_FILE).is_file());
+    }
+
+    #[test]
+    fn test_palettes_replace_theme_colours() {
+        let themes = Themes::load();
+        let mut settings = Settings::default();
+        let theme = themes.get(DEFAULT_THEME);
+        for palette in Palette::ALL {
+            settings.palette = palette;
+            let colours = themes.with_palette(DEFAULT_THEME, &settings);
+            if let Some(name) = palette.name() {
+                assert!(themes.palettes.contains_key(name), "{name}");
+                // enemies are never red next to blue players anymore
+                assert_ne!(colours.enemy, theme.enemy, "{name}");
+                assert_ne!(colours.coin, colours.explosion, "{name}");
+                assert_eq!(colours.players.len(), PLAYERS, "{name}");
+            } else {
+                assert_eq!(colours.enemy, theme.enemy);
+            }
+        }
+        // what the palette leaves alone comes from the theme
+        settings.palette = Palette::Deuteranopia;
+        let colours = themes.with_palette("ice", &settings);
+        assert_eq!(colours.wall, themes.get("ice").wall);
+    }
+
+    /// Whether the explosion of a bomb at the player's feet is drawn as a cross
+    fn explosion_is_cross(settings: Settings) -> bool {
+        let mut harness = Harness::new("o  e\n");
+        harness.app.insert_resource(settings);
+        harness.tap(0, Action::PlaceBomb);
+        let exploded = harness.run_until(200, |world| {
+            world.query::<&BombExplosion>().iter(world).next().is_some()
+        });
+        assert!(exploded, "the bomb never went off");
+        harness.tick();
+        let cue = harness.resource::<MeshHandles>().explosion_cue.clone();
+        let world = &mut harness.app.world;
+        let mut explosions = world.query_filtered::<&Handle<Mesh>, With<BombExplosion>>();
+        explosions.iter(world).all(|mesh| *mesh == cue)
+    }
+
+    #[test]
+    fn test_shape_cues_are_a_setting_of_their_own() {
+        let palette = Settings {
+            palette: Palette::Deuteranopia,
+            ..default()
+        };
+        assert!(!explosion_is_cross(palette));
+        let cues = Settings {
+            shape_cues: true,
+            ..default()
+        };
+        assert!(explosion_is_cross(cues));
+    }
+
+    #[test]
+    fn test_menus_go_back_to_the_default_theme() {
+        let mut harness = Harness::new("o  e\n");
+        harness.app.insert_resource(ClearColor(Color::BLACK));
+        harness.app.world.resource_mut::<Level>().theme = "lava";
+        harness.tick();
+        let lava = harness.resource::<Themes>().get("lava").clear;
+        assert_eq!(harness.resource::<ClearColor>().0, color(lava));
+        assert_eq!(harness.resource::<ActiveTheme>().0.clear, lava);
+
+        harness
+            .app
+            .world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::Menu)
+            .unwrap();
+        harness.tick();
+        harness.tick();
+        let classic = harness.resource::<Themes>().get(DEFAULT_THEME).clear;
+        assert_eq!(harness.resource::<ClearColor>().0, color(classic));
+        assert_eq!(harness.resource::<ActiveTheme>().0.clear, classic);
+    }
+}
@@ -1,7 +1,9 @@
 use std::ops::Mul;
 
-use super::statics::{sizes, LEVELS, LEVEL_BOMBS};
+use super::statics::{sizes, ARENA_BOMBS, BOMB_SECONDS, LEVELS, LEVEL_BOMBS};
+use crate::settings::PLAYERS;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 // ################################################################################
 // General Helper Types
@@ -14,7 +16,8 @@ pub enum BlockType {
     WallSmallH,
     Coin,
     Enemy,
-    Player,
+    /// The spawn point of the player with the given index
+    Player(usize),
     Space,
     Exit,
 }
@@ -29,7 +32,7 @@ impl BlockType {
             BlockType::WallSmallH => Vec3::new(v_b.x, v_b.y, v_s),
             BlockType::Coin => sizes::coin,
             BlockType::Enemy => sizes::enemy,
-            BlockType::Player => sizes::enemy,
+            BlockType::Player(_) => sizes::enemy,
             BlockType::Space => sizes::space,
             BlockType::Exit => sizes::space,
         }
@@ -41,6 +44,21 @@ impl BlockType {
             BlockType::WallBig | BlockType::WallSmallH | BlockType::WallSmallV
         )
     }
+
+    /// The character of the block in the level data
+    pub fn symbol(&self) -> char {
+        match self {
+            BlockType::Coin => '*',
+            BlockType::WallBig => '#',
+            BlockType::WallSmallH => '-',
+            BlockType::WallSmallV => '|',
+            BlockType::Player(0) => 'o',
+            BlockType::Player(_) => 'O',
+            BlockType::Enemy => 'x',
+            BlockType::Space => ' ',
+            BlockType::Exit => 'e',
+        }
+    }
 }
 
 impl From<char> for BlockType {
@@ -51,7 +69,8 @@ impl From<char> for BlockType {
             '#' => WallBig,
             '-' => WallSmallH,
             '|' => WallSmallV,
-            'o' => Player,
+            'o' => Player(0),
+            'O' => Player(1),
             'x' => Enemy,
             ' ' => Space,
             'e' => Exit,
@@ -60,7 +79,7 @@ impl From<char> for BlockType {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: usize,
     pub z: usize,
@@ -72,18 +91,18 @@ impl Position {
     }
 
     pub fn apply_direction(&mut self, direction: &BoardDirection) {
-        let x = self.x as i8 + direction.x;
+        let x = self.x as isize + direction.x as isize;
         if x >= 0 {
             self.x = x as usize;
         }
-        let z = self.z as i8 + direction.z;
+        let z = self.z as isize + direction.z as isize;
         if z >= 0 {
             self.z = z as usize;
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct BoardDirection {
     pub x: i8,
     pub z: i8,
@@ -126,6 +145,82 @@ pub struct Block {
 
 pub struct CurrentLevel(pub usize);
 
+/// The level a new game starts with
+pub struct StartLevel(pub usize);
+
+/// Level data that is played instead of the campaign level of `StartLevel`.
+/// It exists for the small levels of the test harness; the attract demo plays
+/// campaign levels and empties it when it ends, so that no game started from
+/// the menus picks it up
+#[derive(Default)]
+pub struct CustomLevel(pub Option<String>);
+
+/// Set while the simulated tick uses guessed inputs of the other instance of
+/// an online match. Nothing that can't be rolled back may happen then, sounds
+/// and animations go through `Effects`
+#[derive(Default)]
+pub struct PredictedInputs(pub bool);
+
+/// Set while nobody plays the game themselves, like in the attract mode or in
+/// headless runs. Neither progress nor the run are saved then
+#[derive(Default)]
+pub struct Unattended(pub bool);
+
+/// What kind of game is played on the levels
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Campaign,
+    /// Two players share the campaign levels
+    Coop,
+    /// Two players fight on arena levels until one is left
+    Versus,
+}
+
+impl GameMode {
+    pub fn players(&self) -> usize {
+        match self {
+            GameMode::Campaign => 1,
+            GameMode::Coop | GameMode::Versus => PLAYERS,
+        }
+    }
+}
+
+/// The state of a versus match over all of its rounds
+#[derive(Clone)]
+pub struct Versus {
+    pub round: usize,
+    pub scores: Vec<Score>,
+    pub wins: Vec<usize>,
+    /// Ticks since the current round was decided
+    pub round_over_ticks: u32,
+}
+
+impl Default for Versus {
+    fn default() -> Self {
+        Self {
+            round: 0,
+            scores: vec![Score::with_bombs(ARENA_BOMBS); PLAYERS],
+            wins: vec![0; PLAYERS],
+            round_over_ticks: 0,
+        }
+    }
+}
+
+impl Versus {
+    /// The arena of the current round
+    pub fn arena(&self) -> usize {
+        self.round % super::statics::ARENAS.len()
+    }
+
+    /// The player that won enough rounds to take the match
+    pub fn winner(&self) -> Option<usize> {
+        self.wins
+            .iter()
+            .position(|wins| *wins >= super::statics::MATCH_WINS)
+    }
+}
+
 impl CurrentLevel {
     pub fn next(&self) -> Option<CurrentLevel> {
         if (self.0 + 1) >= LEVELS.len() {
@@ -142,14 +237,25 @@ impl CurrentLevel {
 #[derive(Component)]
 pub struct Wall;
 
+/// The walls of a chunk of tiles in one mesh, with the see-through ones in
+/// the mesh of another entity
+#[derive(Component)]
+pub struct WallChunk {
+    pub walls: Vec<Block>,
+    /// The positions of the walls that are see-through
+    pub hidden: Vec<Position>,
+    pub see_through: Entity,
+}
+
 #[derive(Component)]
 pub struct Enemy;
 
 #[derive(Component)]
 pub struct Coin;
 
-#[derive(Component)]
-pub struct Player;
+/// The index of the player, which also selects its controls and colour
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Player(pub usize);
 
 #[derive(Component, Debug)]
 pub struct Location(pub Position);
@@ -157,15 +263,28 @@ pub struct Location(pub Position);
 #[derive(Component)]
 pub struct Floor;
 
-#[derive(Component, Default, Debug)]
+/// Players and enemies. They keep their entity after dying, so that a rollback
+/// can bring them back
+#[derive(Component)]
+pub struct Actor;
+
+#[derive(Component, Default, Debug, Clone)]
 pub struct Movement {
     pub value: f32,
     pub direction: BoardDirection,
 }
 
+/// The last direction the player asked for, taken at the next tile where it is free
+#[derive(Component, Default, Debug, Clone)]
+pub struct TurnBuffer {
+    pub direction: Option<BoardDirection>,
+}
+
 #[derive(Component)]
 pub struct Wobbles(pub f32);
 
+/// Nothing reads the size yet, it is kept on the entities for later use
+#[allow(dead_code)]
 #[derive(Component, Default)]
 pub struct Size(pub Vec3);
 
@@ -178,44 +297,87 @@ pub struct Exit;
 #[derive(Component)]
 pub struct ExitLight;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Score {
+    pub points: usize,
     pub coins: usize,
-    pub moves: usize,
     pub bombs: usize,
 }
 
-impl Default for Score {
-    fn default() -> Self {
+impl Score {
+    pub fn new(level: usize) -> Self {
+        Score::with_bombs(LEVEL_BOMBS[level])
+    }
+
+    pub fn with_bombs(bombs: usize) -> Self {
         Self {
+            points: 0,
             coins: 0,
-            moves: 0,
-            bombs: LEVEL_BOMBS[0],
+            bombs,
         }
     }
 }
 
+impl Default for Score {
+    fn default() -> Self {
+        Score::new(0)
+    }
+}
+
 #[derive(Component)]
 pub struct Bomb(pub f32);
 
 impl Bomb {
     pub fn new() -> Self {
-        // Default bomb time is 2.5 seconds until explode
-        Bomb(1.5)
+        Bomb(BOMB_SECONDS)
     }
 }
 
+/// The player that placed a bomb
 #[derive(Component)]
-pub struct BombExplosion;
+pub struct BombOwner(pub usize);
+
+/// An explosion tile, holding the bomb it originated from and who placed it
+#[derive(Component)]
+pub struct BombExplosion {
+    pub bomb: Entity,
+    pub owner: usize,
+    /// Ticks until the tile is gone
+    pub ticks: u32,
+}
 
 #[derive(Component)]
 pub struct LevelItem;
 
+/// A coin or an enemy that is gone for good once its animation is done
+#[derive(Component)]
+pub struct DespawnAfterAnimation;
+
+/// What follows a dying player or the last one entering the exit, once its
+/// animation had the time to play
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Ending {
+    /// A player finished dying
+    PlayerGone,
+    /// The last player disappeared in the exit
+    CompleteLevel,
+}
+
 // ################################################################################
 // Events
 // ################################################################################
 
 pub struct ShowLevelExitEvent;
 
+pub struct LevelCompletedEvent;
+
 pub struct GoNextLevelEvent;
 
-pub struct PlayerDiedEvent;
+/// Holds the index of the player
+pub struct PlayerDiedEvent(pub usize);
+
+/// The dying animation of a player is over
+pub struct PlayerGoneEvent;
+
+/// Only one player (or none) is left in a versus round
+pub struct RoundOverEvent;
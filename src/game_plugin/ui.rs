@@ -1,6 +1,18 @@
 use bevy::prelude::*;
 
-use super::types::{CurrentLevel, Score};
+use crate::{
+    net_plugin::NetSession,
+    save::SaveData,
+    settings::{Action, Settings},
+    GameState,
+};
+
+use super::level::Level;
+use super::scoring::Tally;
+use super::statics::LEVEL_GOALS;
+use super::types::{
+    CurrentLevel, GameMode, GoNextLevelEvent, LevelCompletedEvent, Score, Unattended, Versus,
+};
 
 #[derive(Component)]
 pub struct UiComponent;
@@ -14,6 +26,9 @@ pub struct LevelLabel;
 #[derive(Component)]
 pub struct PointLabel;
 
+#[derive(Component)]
+pub struct TallyComponent;
+
 pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
         .spawn_bundle(NodeBundle {
@@ -69,14 +84,220 @@ pub fn cleanup_ui(mut commands: Commands, query: Query<Entity, With<UiComponent>
     }
 }
 
-pub fn update_ui_bombs(score: Res<Score>, mut query: Query<&mut Text, With<BombLabel>>) {
-    query.single_mut().sections[0].value = format!("Bombs x{}", score.bombs);
+/// In versus, every player has their own entry
+fn per_player(versus: &Versus, label: impl Fn(&Score) -> String) -> String {
+    versus
+        .scores
+        .iter()
+        .enumerate()
+        .map(|(player, score)| format!("P{} {}", player + 1, label(score)))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+pub fn update_ui_bombs(
+    score: Res<Score>,
+    mode: Res<GameMode>,
+    versus: Res<Versus>,
+    mut query: Query<&mut Text, With<BombLabel>>,
+) {
+    query.single_mut().sections[0].value = match *mode {
+        GameMode::Versus => per_player(&versus, |score| format!("x{}", score.bombs)),
+        _ => format!("Bombs x{}", score.bombs),
+    };
+}
+
+pub fn update_ui_score(
+    score: Res<Score>,
+    mode: Res<GameMode>,
+    versus: Res<Versus>,
+    mut query: Query<&mut Text, With<PointLabel>>,
+) {
+    query.single_mut().sections[0].value = match *mode {
+        GameMode::Versus => per_player(&versus, |score| format!("#{}", score.points)),
+        _ => format!("#{}", score.points),
+    };
+}
+
+pub fn update_ui_level(
+    level: Res<CurrentLevel>,
+    mode: Res<GameMode>,
+    versus: Res<Versus>,
+    mut query: Query<&mut Text, With<LevelLabel>>,
+) {
+    query.single_mut().sections[0].value = match *mode {
+        GameMode::Versus => format!("Round {}", versus.round + 1),
+        _ => format!("Level {}", level.0 + 1),
+    };
+}
+
+/// Once the player left the level, show a breakdown of the points
+#[allow(clippy::too_many_arguments)]
+pub fn show_tally(
+    mut commands: Commands,
+    mut reader: EventReader<LevelCompletedEvent>,
+    asset_server: Res<AssetServer>,
+    level: Res<Level>,
+    current_level: Res<CurrentLevel>,
+    mut score: ResMut<Score>,
+    mut save: ResMut<SaveData>,
+    unattended: Res<Unattended>,
+    settings: Res<Settings>,
+) {
+    for _ in reader.iter() {
+        let tally = Tally::new(&level, &LEVEL_GOALS[current_level.0], &score);
+        score.points += tally.bonus();
+        if !unattended.0 {
+            save.record_completion(current_level.0, tally.stars());
+            save.save();
+        }
+
+        let stats = &level.stats;
+        let lines = [
+            (format!("Coins x{}", stats.coins), tally.coins),
+            (
+                format!(
+                    "Enemies x{} (best combo x{})",
+                    stats.kills, stats.best_combo
+                ),
+                tally.kills,
+            ),
+            (format!("Time {:.1}s", stats.time), tally.time),
+            (format!("Unused bombs x{}", score.bombs), tally.bombs),
+            (format!("Moves x{}", stats.moves), tally.moves),
+            ("Total".to_string(), tally.total()),
+        ];
+
+        let font = asset_server.load("fonts/Archivo-SemiBold.ttf");
+        let text_style = |color: Color, font_size: f32| TextStyle {
+            font: font.clone(),
+            font_size,
+            color,
+        };
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    flex_direction: FlexDirection::ColumnReverse,
+                    ..default()
+                },
+                color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                    format!("Level {} done", current_level.0 + 1),
+                    text_style(Color::rgb(1.0, 1.0, 0.0), 40.0),
+                ));
+                for (label, points) in lines {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        format!("{label}: {points}"),
+                        text_style(Color::rgb(0.9, 0.9, 0.9), 20.0),
+                    ));
+                }
+                parent.spawn_bundle(TextBundle::from_section(
+                    star_label(tally.stars()),
+                    text_style(Color::rgb(1.0, 1.0, 0.0), 40.0),
+                ));
+                for (goal, met) in tally.goals.iter() {
+                    let color = if *met {
+                        Color::rgb(1.0, 1.0, 0.0)
+                    } else {
+                        Color::rgb(0.5, 0.5, 0.5)
+                    };
+                    parent.spawn_bundle(TextBundle::from_section(
+                        goal.description(),
+                        text_style(color, 20.0),
+                    ));
+                }
+                parent.spawn_bundle(TextBundle::from_section(
+                    format!(
+                        "Press {} to continue",
+                        settings.bindings(0).describe(Action::Confirm)
+                    ),
+                    text_style(Color::rgb(0.0, 0.9, 0.9), 20.0),
+                ));
+            })
+            .insert(TallyComponent)
+            .insert(UiComponent);
+    }
+}
+
+pub fn tally_input(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    query: Query<Entity, With<TallyComponent>>,
+    mut writer: EventWriter<GoNextLevelEvent>,
+) {
+    if query.is_empty() || !actions.just_pressed(Action::Confirm) {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    writer.send(GoNextLevelEvent);
+}
+
+/// Earned stars are filled, missing ones are dashes
+pub fn star_label(stars: usize) -> String {
+    (0..3)
+        .map(|i| if i < stars { "*" } else { "-" })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Component)]
+pub struct PauseComponent;
+
+/// An online match can't be paused, the other player would keep waiting. The
+/// press is used up, otherwise the pause would see it and resume in the same frame
+pub fn pause_input(
+    mut actions: ResMut<Input<Action>>,
+    session: Option<Res<NetSession>>,
+    mut app_state: ResMut<State<GameState>>,
+) {
+    if session.is_none() && actions.clear_just_pressed(Action::Pause) {
+        app_state.push(GameState::Paused).unwrap();
+    }
 }
 
-pub fn update_ui_score(score: Res<Score>, mut query: Query<&mut Text, With<PointLabel>>) {
-    query.single_mut().sections[0].value = format!("#{}", score.coins);
+pub fn unpause_input(mut actions: ResMut<Input<Action>>, mut app_state: ResMut<State<GameState>>) {
+    if actions.clear_just_pressed(Action::Pause) {
+        app_state.pop().unwrap();
+    }
+}
+
+pub fn setup_pause(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Paused",
+                TextStyle {
+                    font: asset_server.load("fonts/Archivo-Bold.ttf"),
+                    font_size: 50.0,
+                    color: Color::rgb(1.0, 1.0, 0.0),
+                },
+            ));
+        })
+        .insert(PauseComponent);
 }
 
-pub fn update_ui_level(level: Res<CurrentLevel>, mut query: Query<&mut Text, With<LevelLabel>>) {
-    query.single_mut().sections[0].value = format!("Level {}", level.0 + 1);
+pub fn cleanup_pause(mut commands: Commands, query: Query<Entity, With<PauseComponent>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
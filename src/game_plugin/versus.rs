@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+
+use crate::{
+    actions_plugin::PlayerActions,
+    settings::{Action, Settings},
+    GameState,
+};
+
+use super::level::Level;
+use super::statics::{ARENA_BOMBS, FPS, ROUND_OVER_SECONDS};
+use super::types::{
+    CurrentLevel, GameMode, LevelItem, PredictedInputs, RoundOverEvent, Score, Versus,
+};
+use super::ui::UiComponent;
+
+#[derive(Component)]
+pub struct RoundComponent;
+
+/// The score a player collects into. Outside of versus, all players share one
+pub fn player_score<'a>(
+    mode: GameMode,
+    score: &'a mut Score,
+    versus: &'a mut Versus,
+    player: usize,
+) -> &'a mut Score {
+    match mode {
+        GameMode::Versus => &mut versus.scores[player],
+        _ => score,
+    }
+}
+
+/// Shows the standings once the round is decided
+pub fn show_round_result(
+    mut commands: Commands,
+    mut reader: EventReader<RoundOverEvent>,
+    query: Query<(), With<RoundComponent>>,
+    asset_server: Res<AssetServer>,
+    level: Res<Level>,
+    versus: Res<Versus>,
+    settings: Res<Settings>,
+) {
+    // both players can die at once, but the round only ends one time
+    if reader.iter().last().is_none() || !query.is_empty() {
+        return;
+    }
+    let title = match (versus.winner(), level.player_positions.keys().next()) {
+        (Some(winner), _) => format!("Player {} wins the match", winner + 1),
+        (None, Some(winner)) => format!("Player {} wins round {}", winner + 1, versus.round + 1),
+        (None, None) => format!("Round {} is a draw", versus.round + 1),
+    };
+    let next = match versus.winner() {
+        Some(_) => "to return to the menu",
+        None => "for the next round",
+    };
+
+    let font = asset_server.load("fonts/Archivo-SemiBold.ttf");
+    let text_style = |color: Color, font_size: f32| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                title,
+                text_style(Color::rgb(1.0, 1.0, 0.0), 40.0),
+            ));
+            for (player, (wins, score)) in versus.wins.iter().zip(&versus.scores).enumerate() {
+                parent.spawn_bundle(TextBundle::from_section(
+                    format!("Player {}: {} wins, #{}", player + 1, wins, score.points),
+                    text_style(Color::rgb(0.9, 0.9, 0.9), 20.0),
+                ));
+            }
+            parent.spawn_bundle(TextBundle::from_section(
+                format!(
+                    "Press {} {next}",
+                    settings.bindings(0).describe(Action::Confirm)
+                ),
+                text_style(Color::rgb(0.0, 0.9, 0.9), 20.0),
+            ));
+        })
+        .insert(RoundComponent)
+        .insert(UiComponent);
+}
+
+/// Counts the win of the survivor and starts the next round on the next arena,
+/// or goes back to the menu once the match is won. This is part of the
+/// simulation, so that online matches decide the round on the same tick. Any
+/// player can confirm, but only with inputs that are certain
+#[allow(clippy::too_many_arguments)]
+pub fn round_input(
+    mut commands: Commands,
+    actions: Res<PlayerActions>,
+    predicted: Res<PredictedInputs>,
+    level: Res<Level>,
+    mode: Res<GameMode>,
+    query: Query<Entity, With<RoundComponent>>,
+    level_items: Query<Entity, With<LevelItem>>,
+    mut versus: ResMut<Versus>,
+    mut app_state: ResMut<State<GameState>>,
+) {
+    if *mode != GameMode::Versus || !level.finished {
+        return;
+    }
+    if versus.round_over_ticks == 0 {
+        if let Some(winner) = level.player_positions.keys().next() {
+            versus.wins[*winner] += 1;
+        }
+    }
+    versus.round_over_ticks += 1;
+    let confirm = actions
+        .0
+        .iter()
+        .any(|input| input.just_pressed(Action::Confirm));
+    if (versus.round_over_ticks as f32) < ROUND_OVER_SECONDS * FPS || !confirm || predicted.0 {
+        return;
+    }
+    // leaving the running game cleans up the level
+    if versus.winner().is_some() {
+        app_state.set(GameState::Menu).unwrap();
+        return;
+    }
+    for entity in query.iter().chain(level_items.iter()) {
+        commands.entity(entity).despawn_recursive();
+    }
+    versus.round += 1;
+    versus.round_over_ticks = 0;
+    // every round starts with a full bomb stock
+    for score in versus.scores.iter_mut() {
+        score.bombs = ARENA_BOMBS;
+    }
+    commands.insert_resource(Level::arena(versus.arena()));
+    commands.insert_resource(CurrentLevel(versus.arena()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_plugin::statics::MATCH_WINS;
+    use crate::game_plugin::types::PlayerDiedEvent;
+    use crate::headless_plugin::harness::Harness;
+
+    #[test]
+    fn test_match_ends_after_enough_wins() {
+        let mut harness = Harness::with_mode("", GameMode::Versus);
+        let world = &mut harness.app.world;
+        world.resource_mut::<Versus>().wins[0] = MATCH_WINS - 1;
+        world.resource_mut::<Level>().player_positions.remove(&1);
+        world.send_event(PlayerDiedEvent(1));
+        let decided = harness.run_until(200, |world| {
+            world.resource::<Versus>().round_over_ticks as f32 >= ROUND_OVER_SECONDS * FPS
+        });
+        assert!(decided, "the round was never decided");
+        assert_eq!(harness.resource::<Versus>().winner(), Some(0));
+        harness.tap(0, Action::Confirm);
+        let left = harness.run_until(10, |world| {
+            world.resource::<State<GameState>>().current() == &GameState::Menu
+        });
+        assert!(left, "the match didn't end");
+    }
+}
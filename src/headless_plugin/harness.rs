@@ -0,0 +1,105 @@
+use bevy::{
+    input::{keyboard::KeyboardInput, ButtonState},
+    prelude::*,
+};
+
+use crate::{
+    game_plugin::{CustomLevel, GameMode},
+    save::SaveData,
+    settings::{Action, Settings},
+    GameState,
+};
+
+use super::{tick, HeadlessPlugin};
+
+/// Plays a level headless for the tests of the game. Input goes through the
+/// keyboard with the default bindings, like a player would press the keys
+pub struct Harness {
+    pub app: App,
+}
+
+impl Harness {
+    /// A campaign game of one player on the given level data, started and ready
+    /// to take input. Nothing of the player's saved progress or settings is used
+    pub fn new(level: &str) -> Self {
+        Harness::with_mode(level, GameMode::Campaign)
+    }
+
+    pub fn with_mode(level: &str, mode: GameMode) -> Self {
+        Harness::start(mode, 0, Some(level))
+    }
+
+    /// The level of the campaign, with its own amount of bombs
+    pub fn campaign(level: usize) -> Self {
+        Harness::start(GameMode::Campaign, level, None)
+    }
+
+    fn start(mode: GameMode, level: usize, custom: Option<&str>) -> Self {
+        let mut app = crate::app(Some(HeadlessPlugin {
+            mode,
+            level,
+            stdio_agent: false,
+        }));
+        app.insert_resource(CustomLevel(custom.map(str::to_string)))
+            .insert_resource(SaveData::default())
+            .insert_resource(Settings::default());
+        let mut harness = Self { app };
+        let running = harness.run_until(60, |world| {
+            world.resource::<State<GameState>>().current() == &GameState::Running
+        });
+        assert!(running, "the level never started");
+        harness
+    }
+
+    fn key(&self, player: usize, action: Action) -> KeyCode {
+        let settings = self.app.world.resource::<Settings>();
+        match settings.bindings(player).keys(action).first() {
+            Some(n) => *n,
+            None => panic!("{} has no key for player {player}", action.label()),
+        }
+    }
+
+    fn send_key(&mut self, player: usize, action: Action, state: ButtonState) {
+        let key = self.key(player, action);
+        self.app.world.send_event(KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key),
+            state,
+        });
+    }
+
+    /// Holds the key of the action until it is released
+    pub fn press(&mut self, player: usize, action: Action) {
+        self.send_key(player, action, ButtonState::Pressed);
+    }
+
+    pub fn release(&mut self, player: usize, action: Action) {
+        self.send_key(player, action, ButtonState::Released);
+    }
+
+    /// Presses the key for a single tick
+    pub fn tap(&mut self, player: usize, action: Action) {
+        self.press(player, action);
+        self.tick();
+        self.release(player, action);
+    }
+
+    pub fn tick(&mut self) {
+        tick(&mut self.app);
+    }
+
+    /// Runs until the condition holds, and returns false if that takes longer than `max` ticks
+    pub fn run_until(&mut self, max: usize, mut condition: impl FnMut(&mut World) -> bool) -> bool {
+        for _ in 0..max {
+            if condition(&mut self.app.world) {
+                return true;
+            }
+            self.tick();
+        }
+        condition(&mut self.app.world)
+    }
+
+    pub fn resource<T: Send + Sync + 'static>(&self) -> &T {
+        self.app.world.resource::<T>()
+    }
+}
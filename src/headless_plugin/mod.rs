@@ -0,0 +1,154 @@
+#[cfg(test)]
+pub mod harness;
+
+use bevy::{
+    app::AppExit,
+    asset::AssetPlugin,
+    audio::AudioSink,
+    ecs::event::ManualEventReader,
+    hierarchy::HierarchyPlugin,
+    input::InputPlugin,
+    prelude::*,
+    time::{create_time_channels, TimeSender},
+    transform::TransformPlugin,
+    utils::Instant,
+    window::WindowCloseRequested,
+};
+use std::{env, time::Duration};
+
+use crate::{
+    agent_plugin::{AgentControl, Outcome, StdioAgent},
+    game_plugin::{
+        GameMode, LevelCompletedEvent, RoundOverEvent, StartLevel, Unattended, FPS, LEVELS,
+    },
+    GameState,
+};
+
+/// Runs the game without a window or sound, given on the command line as
+/// `--headless`. Every update is one tick, and runs as soon as the one before
+/// is done. The run ends with the level it started with
+pub struct HeadlessPlugin {
+    pub mode: GameMode,
+    pub level: usize,
+    /// Whether an agent on stdin and stdout plays all players, see `--agent`
+    pub stdio_agent: bool,
+}
+
+impl HeadlessPlugin {
+    /// What to play, from `--mode <campaign|coop|versus>` and `--level <n>`,
+    /// counting levels from 1 like the menu does
+    pub fn from_args() -> Option<Self> {
+        let args: Vec<String> = env::args().collect();
+        if !args.iter().any(|arg| arg == "--headless") {
+            return None;
+        }
+        let value = |flag: &str| {
+            args.windows(2)
+                .find(|pair| pair[0] == flag)
+                .map(|pair| pair[1].as_str())
+        };
+        let mode = match value("--mode") {
+            Some("coop") => GameMode::Coop,
+            Some("versus") => GameMode::Versus,
+            _ => GameMode::Campaign,
+        };
+        let level = value("--level")
+            .and_then(|n| n.parse::<usize>().ok())
+            .map_or(0, |n| n.clamp(1, LEVELS.len()) - 1);
+        Some(Self {
+            mode,
+            level,
+            stdio_agent: args.iter().any(|arg| arg == "--agent"),
+        })
+    }
+}
+
+/// Feeds the time of every tick to the app
+struct Clock {
+    sender: TimeSender,
+    start: Instant,
+    ticks: u32,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = create_time_channels();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(InputPlugin)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_asset::<ColorMaterial>()
+            .add_asset::<AudioSource>()
+            .add_asset::<AudioSink>()
+            .init_resource::<Audio>()
+            // never sent, but saving the run on close listens for it
+            .add_event::<WindowCloseRequested>()
+            .insert_resource(receiver)
+            .insert_resource(Clock {
+                sender,
+                start: Instant::now(),
+                ticks: 0,
+            })
+            .insert_resource(self.mode)
+            .insert_resource(StartLevel(self.level))
+            .insert_resource(Unattended(true))
+            .add_state(GameState::Game)
+            .add_system_to_stage(CoreStage::Last, drop_sounds)
+            .add_system_to_stage(CoreStage::PostUpdate, end_run)
+            .set_runner(run);
+        if self.stdio_agent {
+            let players = (0..self.mode.players()).collect();
+            app.insert_resource(AgentControl::new(StdioAgent::default(), players));
+        }
+    }
+}
+
+/// Without an audio device, nothing would ever take the sounds out of the queue
+fn drop_sounds(mut audio: ResMut<Audio>) {
+    *audio = Audio::default();
+}
+
+/// Stops once the level is over, instead of waiting for someone to confirm
+fn end_run(
+    mut commands: Commands,
+    mut completed: EventReader<LevelCompletedEvent>,
+    mut round_over: EventReader<RoundOverEvent>,
+    app_state: Res<State<GameState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let outcome = if completed.iter().count() > 0 {
+        Outcome::Completed
+    } else if round_over.iter().count() > 0 {
+        Outcome::RoundOver
+    } else if app_state.current() == &GameState::Lost {
+        Outcome::Lost
+    } else {
+        return;
+    };
+    commands.insert_resource(outcome);
+    exit.send(AppExit);
+}
+
+/// Runs one tick. Time passes by exactly one tick, however long it took
+pub fn tick(app: &mut App) {
+    let mut clock = app.world.resource_mut::<Clock>();
+    let now = clock.start + Duration::from_secs_f32(clock.ticks as f32 / FPS);
+    clock.ticks += 1;
+    // the channel only drops times that were never used
+    let _ = clock.sender.0.try_send(now);
+    app.update();
+}
+
+fn run(mut app: App) {
+    let mut exits = ManualEventReader::<AppExit>::default();
+    loop {
+        tick(&mut app);
+        let events = app.world.resource::<Events<AppExit>>();
+        if exits.iter(events).next().is_some() {
+            return;
+        }
+    }
+}
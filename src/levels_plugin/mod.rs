@@ -0,0 +1,224 @@
+use crate::{
+    actions_plugin::Navigable,
+    game_plugin::{ui::star_label, StartLevel, LEVELS},
+    save::SaveData,
+    settings::Action,
+    GameState,
+};
+use bevy::prelude::*;
+
+pub struct LevelsPlugin;
+
+#[derive(Component)]
+struct LocalEntity;
+
+#[derive(Component, Copy, Clone)]
+enum LevelsButton {
+    Level(usize),
+    Back,
+}
+
+impl LevelsButton {
+    fn normal_color(&self) -> Color {
+        match self {
+            LevelsButton::Level(_) => CARD,
+            LevelsButton::Back => NORMAL_BUTTON,
+        }
+    }
+}
+
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+const CARD: Color = Color::rgb(0.1, 0.1, 0.1);
+const LOCKED_CARD: Color = Color::rgb(0.05, 0.05, 0.05);
+
+impl Plugin for LevelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Levels).with_system(setup))
+            .add_system_set(SystemSet::on_exit(GameState::Levels).with_system(exit))
+            .add_system_set(
+                SystemSet::on_update(GameState::Levels)
+                    .with_system(keyboard_input_system)
+                    .with_system(button_system),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>, save: Res<SaveData>) {
+    let font = asset_server.load("fonts/Archivo-Bold.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Levels",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 50.0,
+                    color: Color::rgb(1.0, 1.0, 0.0),
+                },
+            ));
+            // one card per level
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_wrap: FlexWrap::Wrap,
+                        justify_content: JustifyContent::Center,
+                        margin: UiRect::all(Val::Px(20.0)),
+                        ..default()
+                    },
+                    color: Color::NONE.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for level in 0..LEVELS.len() {
+                        spawn_level_card(parent, &font, &save, level);
+                    }
+                });
+            // back button
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                        // horizontally center child text
+                        justify_content: JustifyContent::Center,
+                        // vertically center child text
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: NORMAL_BUTTON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        "Back",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 20.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ));
+                })
+                .insert(LevelsButton::Back)
+                .insert(Navigable {
+                    color: NORMAL_BUTTON,
+                });
+        })
+        .insert(LocalEntity);
+}
+
+/// Unlocked levels are buttons, locked ones are just a greyed out card
+fn spawn_level_card(parent: &mut ChildBuilder, font: &Handle<Font>, save: &SaveData, level: usize) {
+    let unlocked = save.is_unlocked(level);
+    let style = Style {
+        size: Size::new(Val::Px(140.0), Val::Px(90.0)),
+        margin: UiRect::all(Val::Px(10.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        flex_direction: FlexDirection::ColumnReverse,
+        ..default()
+    };
+    let (title_color, status, status_color) = if unlocked {
+        (
+            Color::rgb(0.9, 0.9, 0.9),
+            star_label(save.stars(level)),
+            Color::rgb(1.0, 1.0, 0.0),
+        )
+    } else {
+        (
+            Color::rgb(0.4, 0.4, 0.4),
+            "Locked".to_string(),
+            Color::rgb(0.4, 0.4, 0.4),
+        )
+    };
+    let children = |parent: &mut ChildBuilder| {
+        parent.spawn_bundle(TextBundle::from_section(
+            format!("Level {}", level + 1),
+            TextStyle {
+                font: font.clone(),
+                font_size: 20.0,
+                color: title_color,
+            },
+        ));
+        parent.spawn_bundle(TextBundle::from_section(
+            status,
+            TextStyle {
+                font: font.clone(),
+                font_size: 30.0,
+                color: status_color,
+            },
+        ));
+    };
+    if unlocked {
+        parent
+            .spawn_bundle(ButtonBundle {
+                style,
+                color: CARD.into(),
+                ..default()
+            })
+            .with_children(children)
+            .insert(LevelsButton::Level(level))
+            .insert(Navigable { color: CARD });
+    } else {
+        parent
+            .spawn_bundle(NodeBundle {
+                style,
+                color: LOCKED_CARD.into(),
+                ..default()
+            })
+            .with_children(children);
+    }
+}
+
+fn exit(mut commands: Commands, destroy_query: Query<Entity, With<LocalEntity>>) {
+    for entity in destroy_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn keyboard_input_system(actions: Res<Input<Action>>, mut app_state: ResMut<State<GameState>>) {
+    if actions.just_pressed(Action::Back) {
+        app_state.set(GameState::Menu).unwrap();
+    }
+}
+
+#[allow(clippy::complexity)]
+fn button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &LevelsButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut app_state: ResMut<State<GameState>>,
+    mut start: ResMut<StartLevel>,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Clicked => {
+                *color = PRESSED_BUTTON.into();
+                match button {
+                    LevelsButton::Level(level) => {
+                        start.0 = *level;
+                        app_state.set(GameState::Loading).unwrap();
+                    }
+                    LevelsButton::Back => app_state.set(GameState::Menu).unwrap(),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = button.normal_color().into();
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    actions_plugin::Navigable,
     game_plugin::{CurrentLevel, Score},
     GameState,
 };
@@ -17,11 +18,7 @@ impl Plugin for LostPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_enter(GameState::Lost).with_system(setup))
             .add_system_set(SystemSet::on_exit(GameState::Lost).with_system(exit))
-            .add_system_set(
-                SystemSet::on_update(GameState::Lost)
-                    .with_system(keyboard_input_system)
-                    .with_system(button_system),
-            );
+            .add_system_set(SystemSet::on_update(GameState::Lost).with_system(button_system));
     }
 }
 
@@ -31,7 +28,7 @@ fn setup(
     score: Res<Score>,
     level: Res<CurrentLevel>,
 ) {
-    let won_text = format!("Level {}, {} Points", level.0 + 1, score.coins);
+    let won_text = format!("Level {}, {} Points", level.0 + 1, score.points);
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -82,6 +79,9 @@ fn setup(
                                     color: Color::rgb(0.9, 0.9, 0.9),
                                 },
                             ));
+                        })
+                        .insert(Navigable {
+                            color: NORMAL_BUTTON,
                         });
                     parent.spawn_bundle(TextBundle::from_section(
                         won_text,
@@ -111,15 +111,6 @@ fn exit(mut commands: Commands, destroy_query: Query<Entity, With<LocalEntity>>)
     }
 }
 
-fn keyboard_input_system(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut app_state: ResMut<State<GameState>>,
-) {
-    if keyboard_input.pressed(KeyCode::Return) {
-        app_state.set(GameState::Game).unwrap();
-    }
-}
-
 #[allow(clippy::complexity)]
 fn button_system(
     mut interaction_query: Query<
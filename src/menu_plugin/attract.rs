@@ -0,0 +1,109 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*, window::CursorMoved};
+
+use crate::{
+    agent_plugin::{AgentControl, Autoplayer},
+    game_plugin::{CustomLevel, GameMode, LevelCompletedEvent, StartLevel, Unattended, LEVELS},
+    settings::Action,
+    GameState,
+};
+
+/// How long the title screen waits for input before it plays a demo
+const IDLE_SECONDS: f32 = 30.0;
+
+/// Counts the time nobody touched anything on the title screen
+pub struct Attract {
+    idle: Timer,
+    /// The demos go through the levels one after another
+    level: usize,
+}
+
+impl Default for Attract {
+    fn default() -> Self {
+        Self {
+            idle: Timer::from_seconds(IDLE_SECONDS, false),
+            level: 0,
+        }
+    }
+}
+
+/// Set while the autoplayer shows a level
+pub struct Demo;
+
+pub fn reset_idle(mut attract: ResMut<Attract>) {
+    attract.idle.reset();
+}
+
+fn any_input(
+    actions: &Input<Action>,
+    keyboard: &Input<KeyCode>,
+    mouse: &Input<MouseButton>,
+) -> bool {
+    actions.get_just_pressed().next().is_some()
+        || keyboard.get_just_pressed().next().is_some()
+        || mouse.get_just_pressed().next().is_some()
+}
+
+/// Starts a demo once the title screen was left alone for long enough
+#[allow(clippy::too_many_arguments)]
+pub fn idle_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut attract: ResMut<Attract>,
+    actions: Res<Input<Action>>,
+    keyboard: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut cursor: EventReader<CursorMoved>,
+    mut app_state: ResMut<State<GameState>>,
+    mut start: ResMut<StartLevel>,
+    mut mode: ResMut<GameMode>,
+    mut unattended: ResMut<Unattended>,
+) {
+    if any_input(&actions, &keyboard, &mouse) || cursor.iter().count() > 0 {
+        attract.idle.reset();
+        return;
+    }
+    if !attract.idle.tick(time.delta()).finished() {
+        return;
+    }
+    start.0 = attract.level;
+    attract.level = (attract.level + 1) % LEVELS.len();
+    *mode = GameMode::Campaign;
+    unattended.0 = true;
+    commands.insert_resource(AgentControl::new(Autoplayer::new(vec![0]), vec![0]));
+    commands.insert_resource(Demo);
+    app_state.set(GameState::Loading).unwrap();
+}
+
+pub fn run_demo(demo: Option<Res<Demo>>) -> ShouldRun {
+    if demo.is_some() {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Any input or the end of the level goes back to the title screen. Runs after
+/// the update stage, where the state already followed keys like pause or quit
+#[allow(clippy::too_many_arguments)]
+pub fn end_demo(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    keyboard: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    mut completed: EventReader<LevelCompletedEvent>,
+    mut app_state: ResMut<State<GameState>>,
+    mut unattended: ResMut<Unattended>,
+    mut custom: ResMut<CustomLevel>,
+) {
+    let over = completed.iter().count() > 0 || app_state.current() == &GameState::Lost;
+    if !over && !any_input(&actions, &keyboard, &mouse) {
+        return;
+    }
+    commands.remove_resource::<AgentControl>();
+    commands.remove_resource::<Demo>();
+    unattended.0 = false;
+    // the next game starts from the campaign again
+    custom.0 = None;
+    // already there if the demo was quit with Escape
+    let _ = app_state.overwrite_replace(GameState::Menu);
+}
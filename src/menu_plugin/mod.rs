@@ -1,4 +1,11 @@
-use crate::GameState;
+mod attract;
+
+use crate::{
+    actions_plugin::Navigable,
+    game_plugin::{GameMode, ResumeRun, SavedRun, StartLevel, Versus},
+    net_plugin::NetConfig,
+    GameState,
+};
 use bevy::prelude::*;
 
 pub struct MenuPlugin;
@@ -6,23 +13,72 @@ pub struct MenuPlugin;
 #[derive(Component)]
 struct LocalEntity;
 
+#[derive(Component, Copy, Clone)]
+enum MenuButton {
+    Start,
+    Resume,
+    Coop,
+    Versus,
+    Online,
+    Levels,
+    Settings,
+}
+
+impl MenuButton {
+    fn label(&self) -> &'static str {
+        match self {
+            MenuButton::Start => "Start",
+            MenuButton::Resume => "Resume",
+            MenuButton::Coop => "Co-op",
+            MenuButton::Versus => "Versus",
+            MenuButton::Online => "Online",
+            MenuButton::Levels => "Levels",
+            MenuButton::Settings => "Settings",
+        }
+    }
+
+    fn target(&self) -> GameState {
+        match self {
+            MenuButton::Start | MenuButton::Resume | MenuButton::Coop | MenuButton::Versus => {
+                GameState::Loading
+            }
+            MenuButton::Online => GameState::Connecting,
+            MenuButton::Levels => GameState::Levels,
+            MenuButton::Settings => GameState::Settings,
+        }
+    }
+}
+
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup))
+        app.init_resource::<attract::Attract>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu)
+                    .with_system(setup)
+                    .with_system(attract::reset_idle),
+            )
             .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(exit))
             .add_system_set(
                 SystemSet::on_update(GameState::Menu)
-                    .with_system(keyboard_input_system)
-                    .with_system(button_system),
+                    .with_system(button_system)
+                    .with_system(attract::idle_system),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                attract::end_demo.with_run_criteria(attract::run_demo),
             );
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    net_config: Option<Res<NetConfig>>,
+) {
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -48,31 +104,28 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ..default()
                 })
                 .with_children(|parent| {
-                    // start button
+                    // buttons
                     parent
-                        .spawn_bundle(ButtonBundle {
+                        .spawn_bundle(NodeBundle {
                             style: Style {
-                                size: Size::new(Val::Px(150.0), Val::Px(65.0)),
-                                // center button
                                 margin: UiRect::all(Val::Auto),
-                                // horizontally center child text
-                                justify_content: JustifyContent::Center,
-                                // vertically center child text
-                                align_items: AlignItems::Center,
                                 ..default()
                             },
-                            color: NORMAL_BUTTON.into(),
+                            color: Color::NONE.into(),
                             ..default()
                         })
                         .with_children(|parent| {
-                            parent.spawn_bundle(TextBundle::from_section(
-                                "Start",
-                                TextStyle {
-                                    font: asset_server.load("fonts/Archivo-Bold.ttf"),
-                                    font_size: 20.0,
-                                    color: Color::rgb(0.9, 0.9, 0.9),
-                                },
-                            ));
+                            spawn_button(parent, &asset_server, MenuButton::Start);
+                            if SavedRun::exists() {
+                                spawn_button(parent, &asset_server, MenuButton::Resume);
+                            }
+                            spawn_button(parent, &asset_server, MenuButton::Coop);
+                            spawn_button(parent, &asset_server, MenuButton::Versus);
+                            if net_config.is_some() {
+                                spawn_button(parent, &asset_server, MenuButton::Online);
+                            }
+                            spawn_button(parent, &asset_server, MenuButton::Levels);
+                            spawn_button(parent, &asset_server, MenuButton::Settings);
                         });
                     // bevy logo (image)
                     parent.spawn_bundle(ImageBundle {
@@ -88,42 +141,85 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(LocalEntity);
 }
 
+fn spawn_button(parent: &mut ChildBuilder, asset_server: &AssetServer, button: MenuButton) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(110.0), Val::Px(65.0)),
+                margin: UiRect::all(Val::Px(6.0)),
+                // horizontally center child text
+                justify_content: JustifyContent::Center,
+                // vertically center child text
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                button.label(),
+                TextStyle {
+                    font: asset_server.load("fonts/Archivo-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        })
+        .insert(button)
+        .insert(Navigable {
+            color: NORMAL_BUTTON,
+        });
+}
+
 fn exit(mut commands: Commands, destroy_query: Query<Entity, With<LocalEntity>>) {
     for entity in destroy_query.iter() {
         commands.entity(entity).despawn_recursive();
     }
 }
 
-fn keyboard_input_system(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut app_state: ResMut<State<GameState>>,
-) {
-    if keyboard_input.pressed(KeyCode::Return) {
-        app_state.set(GameState::Loading).unwrap();
-    }
-}
-
 #[allow(clippy::complexity)]
 fn button_system(
     mut interaction_query: Query<
-        (&Interaction, &mut UiColor, &Children),
+        (&Interaction, &mut UiColor, &Children, &MenuButton),
         (Changed<Interaction>, With<Button>),
     >,
     mut text_query: Query<&mut Text>,
     mut app_state: ResMut<State<GameState>>,
+    mut start: ResMut<StartLevel>,
+    mut resume: ResMut<ResumeRun>,
+    mut mode: ResMut<GameMode>,
+    mut versus: ResMut<Versus>,
 ) {
-    for (interaction, mut color, children) in &mut interaction_query {
+    for (interaction, mut color, children, button) in &mut interaction_query {
         let mut text = text_query.get_mut(children[0]).unwrap();
         match *interaction {
             Interaction::Clicked => {
                 *color = PRESSED_BUTTON.into();
-                app_state.set(GameState::Loading).unwrap();
+                // the start button always begins with the first level
+                start.0 = 0;
+                *mode = GameMode::Campaign;
+                match button {
+                    MenuButton::Resume => {
+                        resume.0 = SavedRun::take();
+                        if let Some(run) = &resume.0 {
+                            *mode = run.mode;
+                        }
+                    }
+                    MenuButton::Coop => *mode = GameMode::Coop,
+                    MenuButton::Versus => {
+                        *mode = GameMode::Versus;
+                        *versus = Versus::default();
+                    }
+                    _ => {}
+                }
+                app_state.set(button.target()).unwrap();
             }
             Interaction::Hovered => {
                 *color = HOVERED_BUTTON.into();
             }
             Interaction::None => {
-                text.sections[0].value = "Start".to_string();
+                text.sections[0].value = button.label().to_string();
                 *color = NORMAL_BUTTON.into();
             }
         }
@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+use std::net::{SocketAddr, UdpSocket};
+
+/// A connection to the other instance that may lose or reorder datagrams
+pub trait Link: Send + Sync {
+    fn send(&self, data: &[u8]);
+    /// The next datagram that arrived, without waiting for one
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+pub struct UdpLink {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl UdpLink {
+    pub fn new(socket: UdpSocket, peer: SocketAddr) -> Self {
+        Self { socket, peer }
+    }
+}
+
+impl Link for UdpLink {
+    fn send(&self, data: &[u8]) {
+        if let Err(e) = self.socket.send_to(data, self.peer) {
+            debug!("Could not send to {}: {e}", self.peer);
+        }
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let mut buffer = [0; 512];
+        while let Ok((len, address)) = self.socket.recv_from(&mut buffer) {
+            if address == self.peer {
+                return Some(buffer[..len].to_vec());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+pub use simulated::SimulatedLink;
+
+#[cfg(test)]
+mod simulated {
+    use super::Link;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    };
+
+    /// Datagrams on their way, with the frame they arrive in
+    struct Channel {
+        queue: Vec<(u64, Vec<u8>)>,
+        seed: u32,
+    }
+
+    impl Channel {
+        fn random(&mut self) -> u32 {
+            // xorshift, so that every run loses the same datagrams
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 17;
+            self.seed ^= self.seed << 5;
+            self.seed
+        }
+    }
+
+    /// The time both ends of a simulated link share, counted in frames
+    pub struct Clock(Arc<AtomicU64>);
+
+    impl Clock {
+        pub fn advance(&self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// One end of an in-process connection, which delays datagrams by a few
+    /// frames and drops some of them, like a bad network would
+    pub struct SimulatedLink {
+        outgoing: Arc<Mutex<Channel>>,
+        incoming: Arc<Mutex<Channel>>,
+        clock: Arc<AtomicU64>,
+        latency: u64,
+        jitter: u32,
+        loss_percent: u32,
+    }
+
+    impl SimulatedLink {
+        pub fn pair(latency: u64, jitter: u32, loss_percent: u32) -> (Self, Self, Clock) {
+            let channel = |seed| {
+                Arc::new(Mutex::new(Channel {
+                    queue: Vec::new(),
+                    seed,
+                }))
+            };
+            let (a, b) = (channel(0x2545_f491), channel(0x9e37_79b9));
+            let clock = Arc::new(AtomicU64::new(0));
+            let end = |outgoing: &Arc<_>, incoming: &Arc<_>| SimulatedLink {
+                outgoing: Arc::clone(outgoing),
+                incoming: Arc::clone(incoming),
+                clock: Arc::clone(&clock),
+                latency,
+                jitter,
+                loss_percent,
+            };
+            (end(&a, &b), end(&b, &a), Clock(Arc::clone(&clock)))
+        }
+    }
+
+    impl Link for SimulatedLink {
+        fn send(&self, data: &[u8]) {
+            let mut channel = self.outgoing.lock().unwrap();
+            if channel.random() % 100 < self.loss_percent {
+                return;
+            }
+            let delay = self.latency + (channel.random() % (self.jitter + 1)) as u64;
+            let arrival = self.clock.load(Ordering::Relaxed) + delay;
+            channel.queue.push((arrival, data.to_vec()));
+        }
+
+        fn receive(&mut self) -> Option<Vec<u8>> {
+            let now = self.clock.load(Ordering::Relaxed);
+            let mut channel = self.incoming.lock().unwrap();
+            let index = channel
+                .queue
+                .iter()
+                .position(|(arrival, _)| *arrival <= now)?;
+            Some(channel.queue.remove(index).1)
+        }
+    }
+}
@@ -0,0 +1,511 @@
+mod link;
+mod protocol;
+mod relay;
+mod rollback;
+
+use bevy::prelude::*;
+use std::{
+    collections::BTreeMap,
+    env,
+    net::UdpSocket,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    actions_plugin::{PlayerActions, UpdateActions},
+    game_plugin::{GameMode, GameSnapshot, Simulation, Versus},
+    settings::{Action, Settings, PLAYERS},
+    GameState,
+};
+
+use link::{Link, UdpLink};
+use protocol::{apply_bits, input_bits, Message};
+use rollback::{Rollback, WorldSimulation};
+
+pub use relay::RelayPlugin;
+
+/// In lockstep, local inputs are scheduled this many ticks ahead, so that they
+/// usually arrive in time
+const INPUT_DELAY: u64 = 3;
+/// With rollback, late inputs only cost a correction, so they can be used sooner
+const ROLLBACK_INPUT_DELAY: u64 = 1;
+/// How long to wait for the other instance before the match is given up
+const TIMEOUT: Duration = Duration::from_secs(5);
+const RESEND_INTERVAL: Duration = Duration::from_millis(15);
+const HELLO_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Online versus matches between two instances. By default, each instance
+/// guesses the inputs of the other one and corrects the game once they arrive.
+/// With `--lockstep` on the host, every tick only starts once the inputs of
+/// both players are known
+pub struct NetPlugin;
+
+/// How this instance takes part in an online match, given on the command line
+/// as `--host <address>`, `--host-via <relay address>` or `--join <address>`.
+/// The address to join can be the one of a host or of a relay
+#[derive(Debug, Clone)]
+pub enum NetConfig {
+    Host(String),
+    HostVia(String),
+    Join(String),
+}
+
+impl NetConfig {
+    fn from_args() -> Option<Self> {
+        let args: Vec<String> = env::args().collect();
+        args.windows(2).find_map(|pair| match pair[0].as_str() {
+            "--host" => Some(NetConfig::Host(pair[1].clone())),
+            "--host-via" => Some(NetConfig::HostVia(pair[1].clone())),
+            "--join" => Some(NetConfig::Join(pair[1].clone())),
+            _ => None,
+        })
+    }
+
+    fn address(&self) -> &str {
+        match self {
+            NetConfig::Host(n) | NetConfig::HostVia(n) | NetConfig::Join(n) => n,
+        }
+    }
+
+    /// Only the host decides, the joining instance is told with the welcome
+    fn lockstep() -> bool {
+        env::args().any(|arg| arg == "--lockstep")
+    }
+}
+
+/// A running online match
+pub struct NetSession {
+    link: Box<dyn Link>,
+    /// The host plays the first player, the joining instance the second one
+    local_player: usize,
+    /// The next tick to simulate
+    tick: u64,
+    delay: u64,
+    local: BTreeMap<u64, u8>,
+    remote: BTreeMap<u64, u8>,
+    /// The first tick without the inputs of the other instance
+    confirmed: u64,
+    /// The first tick the other instance is still missing local inputs for
+    acknowledged: u64,
+    last_received: Instant,
+    /// Only used in lockstep
+    inputs: [Input<Action>; PLAYERS],
+    /// Since when lockstep waits for the inputs of the other instance
+    waiting_since: Option<Instant>,
+    last_sent: Option<Instant>,
+    /// Only used with rollback
+    rollback: Option<Rollback<GameSnapshot>>,
+}
+
+impl NetSession {
+    fn new(link: Box<dyn Link>, local_player: usize, lockstep: bool) -> Self {
+        let delay = if lockstep {
+            INPUT_DELAY
+        } else {
+            ROLLBACK_INPUT_DELAY
+        };
+        // nobody can press anything before the first inputs arrive
+        let nothing: BTreeMap<u64, u8> = (0..delay).map(|tick| (tick, 0)).collect();
+        Self {
+            link,
+            local_player,
+            tick: 0,
+            delay,
+            local: nothing.clone(),
+            remote: nothing,
+            confirmed: delay,
+            acknowledged: 0,
+            last_received: Instant::now(),
+            inputs: default(),
+            waiting_since: None,
+            last_sent: None,
+            rollback: (!lockstep).then(Rollback::default),
+        }
+    }
+
+    pub fn is_rollback(&self) -> bool {
+        self.rollback.is_some()
+    }
+
+    /// The simulation doesn't advance on its own, either because rollback steps
+    /// it or because lockstep still waits for the inputs of the current tick
+    pub fn holds_simulation(&self) -> bool {
+        self.is_rollback() || self.waiting_since.is_some()
+    }
+
+    fn send(&self, message: &Message) {
+        self.link.send(&message.encode());
+    }
+
+    /// Schedules the local inputs, unless the ones of that tick were already sent
+    fn schedule(&mut self, bits: u8) {
+        self.local.entry(self.tick + self.delay).or_insert(bits);
+    }
+
+    /// Sends the local inputs the other instance is still missing
+    fn send_inputs(&self) {
+        let first = self.acknowledged;
+        self.send(&Message::Inputs {
+            ack: self.confirmed,
+            first,
+            bits: self.local.range(first..).map(|(_, bits)| *bits).collect(),
+        });
+    }
+
+    /// Sends the inputs at most once every `RESEND_INTERVAL`, no matter how
+    /// many frames are shown in the meantime
+    fn resend_inputs(&mut self) {
+        if self
+            .last_sent
+            .is_none_or(|last| last.elapsed() >= RESEND_INTERVAL)
+        {
+            self.send_inputs();
+            self.last_sent = Some(Instant::now());
+        }
+    }
+
+    fn receive(&mut self) {
+        while let Some(data) = self.link.receive() {
+            match Message::decode(&data) {
+                // the welcome got lost, so the other instance still waits for it
+                Some(Message::Hello) => self.send(&Message::Welcome {
+                    lockstep: !self.is_rollback(),
+                }),
+                Some(Message::Inputs { ack, first, bits }) => {
+                    for (tick, bits) in (first..).zip(bits) {
+                        // ticks that were already forgotten don't come back
+                        if tick >= self.confirmed {
+                            self.remote.insert(tick, bits);
+                        }
+                    }
+                    while self.remote.contains_key(&self.confirmed) {
+                        self.confirmed += 1;
+                    }
+                    self.acknowledged = self.acknowledged.max(ack);
+                    self.last_received = Instant::now();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The inputs of both players in `tick`. Missing remote inputs are guessed
+    /// to be the same as the last known ones
+    fn inputs(&self, tick: u64) -> [u8; PLAYERS] {
+        let mut inputs = [0; PLAYERS];
+        inputs[self.local_player] = self.local.get(&tick).copied().unwrap_or_default();
+        inputs[1 - self.local_player] = self
+            .remote
+            .range(..=tick)
+            .next_back()
+            .map_or(0, |(_, bits)| *bits);
+        inputs
+    }
+
+    /// Drops the inputs before `oldest`, except for what is still needed to
+    /// guess or to send again
+    fn forget(&mut self, oldest: u64) {
+        let keep = oldest.min(self.acknowledged).saturating_sub(1);
+        self.local = self.local.split_off(&keep);
+        self.remote = self.remote.split_off(&oldest.saturating_sub(1));
+    }
+}
+
+/// The socket while waiting for the other instance
+struct Connecting {
+    socket: UdpSocket,
+    last_hello: Option<Instant>,
+}
+
+#[derive(Component)]
+struct LocalEntity;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(config) = NetConfig::from_args() {
+            app.insert_resource(config);
+        }
+        app.add_system_set(SystemSet::on_enter(GameState::Connecting).with_system(setup))
+            .add_system_set(SystemSet::on_exit(GameState::Connecting).with_system(exit))
+            .add_system_set(SystemSet::on_update(GameState::Connecting).with_system(connect_system))
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(end_session))
+            .add_system_to_stage(CoreStage::PreUpdate, lockstep_system.after(UpdateActions))
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                rollback_system.exclusive_system().at_end(),
+            );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<NetConfig>,
+    settings: Res<Settings>,
+) {
+    let bound = match &*config {
+        NetConfig::Host(address) => UdpSocket::bind(address),
+        NetConfig::HostVia(_) | NetConfig::Join(_) => UdpSocket::bind("0.0.0.0:0"),
+    };
+    let text = match bound.and_then(|socket| socket.set_nonblocking(true).map(|_| socket)) {
+        Ok(socket) => {
+            commands.insert_resource(Connecting {
+                socket,
+                last_hello: None,
+            });
+            format!("Waiting for the other player on {}", config.address())
+        }
+        Err(e) => format!("Could not open {}: {e}", config.address()),
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: asset_server.load("fonts/Archivo-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::rgb(1.0, 1.0, 0.0),
+                },
+            ));
+            parent.spawn_bundle(TextBundle::from_section(
+                format!(
+                    "Press {} or Back on a gamepad to go back",
+                    settings.bindings(0).describe(Action::Back)
+                ),
+                TextStyle {
+                    font: asset_server.load("fonts/Archivo-SemiBold.ttf"),
+                    font_size: 20.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        })
+        .insert(LocalEntity);
+}
+
+fn exit(mut commands: Commands, destroy_query: Query<Entity, With<LocalEntity>>) {
+    for entity in destroy_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<Connecting>();
+}
+
+/// The joining instance says hello until the host welcomes it, a host behind
+/// a relay registers until the hello of the other instance comes through
+fn connect_system(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    config: Res<NetConfig>,
+    connecting: Option<ResMut<Connecting>>,
+    mut app_state: ResMut<State<GameState>>,
+    mut mode: ResMut<GameMode>,
+    mut versus: ResMut<Versus>,
+) {
+    if actions.just_pressed(Action::Back) {
+        app_state.set(GameState::Menu).unwrap();
+        return;
+    }
+    let mut connecting = match connecting {
+        Some(n) => n,
+        None => return,
+    };
+    let greeting = match &*config {
+        NetConfig::Host(_) => None,
+        NetConfig::HostVia(address) => Some((Message::Register, address)),
+        NetConfig::Join(address) => Some((Message::Hello, address)),
+    };
+    if let Some((message, address)) = greeting {
+        if connecting
+            .last_hello
+            .is_none_or(|last| last.elapsed() >= HELLO_INTERVAL)
+        {
+            if let Err(e) = connecting
+                .socket
+                .send_to(&message.encode(), address.as_str())
+            {
+                debug!("Could not send to {address}: {e}");
+            }
+            connecting.last_hello = Some(Instant::now());
+        }
+    }
+
+    let mut buffer = [0; 512];
+    while let Ok((len, peer)) = connecting.socket.recv_from(&mut buffer) {
+        let (local_player, lockstep) = match (&*config, Message::decode(&buffer[..len])) {
+            (NetConfig::Host(_) | NetConfig::HostVia(_), Some(Message::Hello)) => {
+                (0, NetConfig::lockstep())
+            }
+            (NetConfig::Join(_), Some(Message::Welcome { lockstep })) => (1, lockstep),
+            _ => continue,
+        };
+        let socket = match connecting.socket.try_clone() {
+            Ok(n) => n,
+            Err(e) => {
+                warn!("Could not use the socket: {e}");
+                return;
+            }
+        };
+        let session = NetSession::new(Box::new(UdpLink::new(socket, peer)), local_player, lockstep);
+        if local_player == 0 {
+            session.send(&Message::Welcome { lockstep });
+        }
+        info!("Playing online with {peer}");
+        commands.insert_resource(session);
+        *mode = GameMode::Versus;
+        *versus = Versus::default();
+        app_state.set(GameState::Loading).unwrap();
+        return;
+    }
+}
+
+fn end_session(mut commands: Commands) {
+    commands.remove_resource::<NetSession>();
+}
+
+/// Replaces the player actions with the ones of the current tick. Until the
+/// inputs of the other instance arrived, the tick is held back and the frame
+/// goes on without it
+fn lockstep_system(
+    mut commands: Commands,
+    session: Option<ResMut<NetSession>>,
+    mut app_state: ResMut<State<GameState>>,
+    mut player_actions: ResMut<PlayerActions>,
+) {
+    let mut session = match session {
+        Some(n) if !n.is_rollback() => n,
+        _ => return,
+    };
+    if app_state.current() != &GameState::Running {
+        return;
+    }
+    // the local devices are read like the ones of the first player
+    let tick = session.tick;
+    session.schedule(input_bits(&player_actions.0[0]));
+
+    session.resend_inputs();
+    session.receive();
+    if session.confirmed <= tick {
+        let since = *session.waiting_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= TIMEOUT {
+            warn!("Lost the connection to the other player");
+            commands.remove_resource::<NetSession>();
+            app_state.set(GameState::Menu).unwrap();
+        }
+        return;
+    }
+
+    let session = &mut *session;
+    session.waiting_since = None;
+    let inputs = session.inputs(tick);
+    for (input, bits) in session.inputs.iter_mut().zip(inputs) {
+        apply_bits(input, bits);
+    }
+    player_actions.0 = session.inputs.clone();
+    session.tick += 1;
+    session.forget(session.tick);
+}
+
+/// Steps the game with rollback. The local inputs are taken like in lockstep,
+/// but nothing ever waits longer than a frame
+fn rollback_system(world: &mut World) {
+    if world.resource::<State<GameState>>().current() != &GameState::Running {
+        return;
+    }
+    let mut rollback = match world
+        .get_resource_mut::<NetSession>()
+        .and_then(|mut session| session.rollback.take())
+    {
+        Some(n) => n,
+        None => return,
+    };
+    let bits = input_bits(&world.resource::<PlayerActions>().0[0]);
+    let lost = world.resource_scope(|world, mut session: Mut<NetSession>| {
+        session.schedule(bits);
+        session.resend_inputs();
+        session.receive();
+        world.resource_scope(|world, mut simulation: Mut<Simulation>| {
+            let mut simulation = WorldSimulation {
+                world,
+                simulation: &mut simulation,
+            };
+            rollback.advance(&mut session, &mut simulation);
+        });
+        session.rollback = Some(rollback);
+        session.last_received.elapsed() >= TIMEOUT
+    });
+    if lost {
+        warn!("Lost the connection to the other player");
+        world.remove_resource::<NetSession>();
+        let mut app_state = world.resource_mut::<State<GameState>>();
+        app_state.set(GameState::Menu).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_sessions_exchange_inputs() {
+        let bind = || UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (a, b) = (bind(), bind());
+        let (a_address, b_address) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+        b.set_nonblocking(true).unwrap();
+        let mut host = NetSession::new(Box::new(UdpLink::new(a, b_address)), 0, true);
+        let mut guest = NetSession::new(Box::new(UdpLink::new(b, a_address)), 1, true);
+
+        host.local.insert(INPUT_DELAY, 0b101);
+        host.send_inputs();
+        thread::sleep(Duration::from_millis(50));
+        guest.receive();
+        assert_eq!(guest.remote.get(&0), Some(&0));
+        assert_eq!(guest.remote.get(&INPUT_DELAY), Some(&0b101));
+        assert_eq!(guest.confirmed, INPUT_DELAY + 1);
+        assert_eq!(host.remote.len(), INPUT_DELAY as usize);
+    }
+
+    #[test]
+    fn test_lockstep_waits_without_blocking() {
+        let (a, b, clock) = link::SimulatedLink::pair(2, 0, 0);
+        let world = |link, player| {
+            let mut world = World::new();
+            world.insert_resource(NetSession::new(Box::new(link), player, true));
+            world.insert_resource(State::new(GameState::Running));
+            world.init_resource::<PlayerActions>();
+            world
+        };
+        let (mut host, mut guest) = (world(a, 0), world(b, 1));
+        let stage = || SystemStage::single_threaded().with_system(lockstep_system);
+        let (mut host_stage, mut guest_stage) = (stage(), stage());
+        let tick = |world: &World| world.resource::<NetSession>().tick;
+
+        // the guest never answers, but every frame still returns right away
+        let started = Instant::now();
+        for _ in 0..20 {
+            host_stage.run(&mut host);
+            clock.advance();
+        }
+        assert!(started.elapsed() < TIMEOUT / 10);
+        assert_eq!(tick(&host), INPUT_DELAY);
+        assert!(host.resource::<NetSession>().holds_simulation());
+
+        for _ in 0..10 {
+            guest_stage.run(&mut guest);
+            host_stage.run(&mut host);
+            clock.advance();
+        }
+        assert!(tick(&host) > INPUT_DELAY);
+        assert!(tick(&guest) > INPUT_DELAY);
+    }
+}
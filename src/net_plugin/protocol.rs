@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+
+use crate::settings::Action;
+
+/// The datagrams both instances exchange. Every message starts with a one byte tag
+#[derive(Debug, PartialEq, Eq)]
+pub enum Message {
+    /// A host behind a relay tells the relay where it is
+    Register,
+    /// The joining instance asks to play
+    Hello,
+    /// The host accepted the joining instance, and tells how the match is synced
+    Welcome { lockstep: bool },
+    /// The inputs of consecutive ticks, starting with `first`. `ack` is the first
+    /// tick the sender still misses inputs for
+    Inputs { ack: u64, first: u64, bits: Vec<u8> },
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::Register => vec![b'R'],
+            Message::Hello => vec![b'H'],
+            Message::Welcome { lockstep } => vec![b'W', *lockstep as u8],
+            Message::Inputs { ack, first, bits } => {
+                let mut data = vec![b'I'];
+                data.extend_from_slice(&ack.to_le_bytes());
+                data.extend_from_slice(&first.to_le_bytes());
+                data.extend_from_slice(bits);
+                data
+            }
+        }
+    }
+
+    /// Anything that isn't a valid message is ignored
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        match data.split_first()? {
+            (b'R', []) => Some(Message::Register),
+            (b'H', []) => Some(Message::Hello),
+            (b'W', [lockstep]) => Some(Message::Welcome {
+                lockstep: *lockstep != 0,
+            }),
+            (b'I', rest) if rest.len() >= 16 => {
+                let (ack, rest) = rest.split_at(8);
+                let (first, bits) = rest.split_at(8);
+                Some(Message::Inputs {
+                    ack: u64::from_le_bytes(ack.try_into().ok()?),
+                    first: u64::from_le_bytes(first.try_into().ok()?),
+                    bits: bits.to_vec(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One bit per pressed action, in the order of `Action::PLAYED`
+pub fn input_bits(input: &Input<Action>) -> u8 {
+    Action::PLAYED
+        .iter()
+        .enumerate()
+        .filter(|(_, action)| input.pressed(**action))
+        .fold(0, |bits, (index, _)| bits | 1 << index)
+}
+
+/// Turns the bits of the next tick into presses and releases, so that
+/// `just_pressed` works the same on both instances
+pub fn apply_bits(input: &mut Input<Action>, bits: u8) {
+    input.clear();
+    for (index, action) in Action::PLAYED.iter().enumerate() {
+        if bits & 1 << index != 0 {
+            input.press(*action);
+        } else {
+            input.release(*action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let inputs = Message::Inputs {
+            ack: 1230,
+            first: 1234,
+            bits: vec![0, 3, 127],
+        };
+        let welcome = Message::Welcome { lockstep: true };
+        for message in [Message::Register, Message::Hello, welcome, inputs] {
+            assert_eq!(Message::decode(&message.encode()), Some(message));
+        }
+        assert_eq!(Message::decode(b"I12345678"), None);
+        assert_eq!(Message::decode(b"W"), None);
+        assert_eq!(Message::decode(b""), None);
+    }
+
+    #[test]
+    fn test_input_bits() {
+        let mut input = Input::default();
+        apply_bits(&mut input, 0b10001);
+        assert!(input.just_pressed(Action::MoveUp));
+        assert!(input.just_pressed(Action::PlaceBomb));
+        assert_eq!(input_bits(&input), 0b10001);
+        apply_bits(&mut input, 0b1);
+        assert!(!input.just_pressed(Action::MoveUp));
+        assert!(input.just_released(Action::PlaceBomb));
+    }
+}
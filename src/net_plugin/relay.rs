@@ -0,0 +1,164 @@
+use bevy::{app::ScheduleRunnerSettings, log::LogPlugin, prelude::*};
+use std::{
+    env,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use super::protocol::Message;
+use super::TIMEOUT;
+
+/// How long the relay sleeps between looking for datagrams
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Forwards the datagrams of an online match between two instances that can't
+/// reach each other, given on the command line as `--relay <address>`. The
+/// host registers with `--host-via <address>`, the other instance joins the
+/// relay like it would join a host. Runs without a window or the game
+pub struct RelayPlugin {
+    pub address: String,
+}
+
+impl RelayPlugin {
+    pub fn from_args() -> Option<Self> {
+        let args: Vec<String> = env::args().collect();
+        args.windows(2)
+            .find(|pair| pair[0] == "--relay")
+            .map(|pair| Self {
+                address: pair[1].clone(),
+            })
+    }
+}
+
+impl Plugin for RelayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ScheduleRunnerSettings::run_loop(POLL_INTERVAL))
+            .add_plugins(MinimalPlugins)
+            .add_plugin(LogPlugin);
+        let bound = UdpSocket::bind(&self.address)
+            .and_then(|socket| socket.set_nonblocking(true).map(|_| socket));
+        match bound {
+            Ok(socket) => {
+                info!("Relaying on {}", self.address);
+                app.insert_resource(RelaySocket(socket))
+                    .init_resource::<Routes>()
+                    .add_system(relay_system);
+            }
+            Err(e) => error!("Could not open {}: {e}", self.address),
+        }
+    }
+}
+
+struct RelaySocket(UdpSocket);
+
+struct Peer {
+    address: SocketAddr,
+    last_seen: Instant,
+}
+
+/// The two instances of the match that is relayed. Whoever stays silent for
+/// longer than `TIMEOUT` is forgotten, so that the next match can start
+#[derive(Default)]
+struct Routes {
+    host: Option<Peer>,
+    guest: Option<Peer>,
+}
+
+impl Routes {
+    /// Where a message from `from` goes, if anywhere. The host registers
+    /// itself, the other instance with its first hello
+    fn route(&mut self, message: &Message, from: SocketAddr, now: Instant) -> Option<SocketAddr> {
+        for peer in [&mut self.host, &mut self.guest] {
+            if peer
+                .as_ref()
+                .is_some_and(|n| now.duration_since(n.last_seen) >= TIMEOUT)
+            {
+                *peer = None;
+            }
+        }
+        let is = |peer: &Option<Peer>| peer.as_ref().is_some_and(|n| n.address == from);
+        let from_host = is(&self.host);
+        let from_guest = is(&self.guest);
+        match message {
+            Message::Register if self.host.is_none() || from_host => {
+                self.host = Some(Peer {
+                    address: from,
+                    last_seen: now,
+                });
+                return None;
+            }
+            Message::Hello if !from_host && (self.guest.is_none() || from_guest) => {
+                self.guest = Some(Peer {
+                    address: from,
+                    last_seen: now,
+                });
+            }
+            _ if from_host || from_guest => {}
+            // somebody else while a match is relayed
+            _ => return None,
+        }
+        let (sender, receiver) = if from_host {
+            (&mut self.host, &self.guest)
+        } else {
+            (&mut self.guest, &self.host)
+        };
+        if let Some(sender) = sender {
+            sender.last_seen = now;
+        }
+        receiver.as_ref().map(|n| n.address)
+    }
+}
+
+fn relay_system(socket: Res<RelaySocket>, mut routes: ResMut<Routes>) {
+    let mut buffer = [0; 512];
+    while let Ok((len, from)) = socket.0.recv_from(&mut buffer) {
+        let message = match Message::decode(&buffer[..len]) {
+            Some(n) => n,
+            None => continue,
+        };
+        let to = match routes.route(&message, from, Instant::now()) {
+            Some(n) => n,
+            None => continue,
+        };
+        if let Err(e) = socket.0.send_to(&buffer[..len], to) {
+            debug!("Could not send to {to}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        let address = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let (host, guest, other) = (address(1), address(2), address(3));
+        let inputs = Message::Inputs {
+            ack: 0,
+            first: 0,
+            bits: vec![],
+        };
+        let mut routes = Routes::default();
+        let now = Instant::now();
+
+        // a hello before the host registered has nowhere to go
+        assert_eq!(routes.route(&Message::Hello, guest, now), None);
+        assert_eq!(routes.route(&Message::Register, host, now), None);
+        assert_eq!(routes.route(&Message::Hello, guest, now), Some(host));
+        let welcome = Message::Welcome { lockstep: false };
+        assert_eq!(routes.route(&welcome, host, now), Some(guest));
+        assert_eq!(routes.route(&inputs, guest, now), Some(host));
+
+        // a third instance can't take part in the match
+        assert_eq!(routes.route(&Message::Hello, other, now), None);
+        assert_eq!(routes.route(&Message::Register, other, now), None);
+        assert_eq!(routes.route(&inputs, other, now), None);
+
+        // once both went silent, the next match can start
+        let later = now + TIMEOUT;
+        assert_eq!(routes.route(&Message::Register, other, later), None);
+        assert_eq!(routes.route(&Message::Hello, guest, later), Some(other));
+        assert_eq!(routes.route(&inputs, host, later), None);
+    }
+}
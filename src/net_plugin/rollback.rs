@@ -0,0 +1,332 @@
+use bevy::prelude::*;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    actions_plugin::PlayerActions,
+    game_plugin::{Effects, GameSnapshot, PredictedInputs, Simulation},
+    settings::PLAYERS,
+};
+
+use super::protocol::apply_bits;
+use super::NetSession;
+
+/// How many ticks the game may run ahead of the inputs of the other instance
+pub const MAX_PREDICTION: u64 = 8;
+
+/// Anything that can be stepped tick by tick, and be put back to an earlier tick
+pub trait Simulate {
+    type Snapshot: Clone;
+    fn save(&mut self) -> Self::Snapshot;
+    fn load(&mut self, snapshot: &Self::Snapshot);
+    /// Runs one tick. The inputs of the tick before tell new presses apart
+    fn step(&mut self, tick: u64, previous: [u8; PLAYERS], inputs: [u8; PLAYERS], predicted: bool);
+    fn can_predict(&self) -> bool;
+    /// The ticks before `confirmed` won't be simulated again
+    fn confirm(&mut self, _confirmed: u64) {}
+}
+
+/// Runs the game ahead of the inputs of the other instance, by guessing that
+/// they don't change. When a guess turns out wrong, the game goes back to that
+/// tick and simulates it again
+pub struct Rollback<S> {
+    /// The state before every tick that may still have to be simulated again
+    snapshots: VecDeque<(u64, S)>,
+    /// The inputs every tick was simulated with, guessed or not
+    history: BTreeMap<u64, [u8; PLAYERS]>,
+}
+
+impl<S> Default for Rollback<S> {
+    fn default() -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            history: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S: Clone> Rollback<S> {
+    /// Corrects wrong guesses, then simulates the next tick. Returns false when
+    /// the game has to wait for the other instance instead
+    pub fn advance(
+        &mut self,
+        session: &mut NetSession,
+        simulation: &mut impl Simulate<Snapshot = S>,
+    ) -> bool {
+        let remote = 1 - session.local_player;
+        let wrong = self.snapshots.iter().position(|(tick, _)| {
+            session
+                .remote
+                .get(tick)
+                .is_some_and(|bits| *bits != self.history[tick][remote])
+        });
+        if let Some(index) = wrong {
+            let (tick, snapshot) = &self.snapshots[index];
+            let now = session.tick;
+            session.tick = *tick;
+            simulation.load(snapshot);
+            self.snapshots.truncate(index);
+            while session.tick < now {
+                self.step(session, simulation);
+            }
+        }
+
+        let ahead = session.tick.saturating_sub(session.confirmed);
+        let waiting = if simulation.can_predict() {
+            ahead >= MAX_PREDICTION
+        } else {
+            session.tick >= session.confirmed
+        };
+        if !waiting {
+            self.step(session, simulation);
+        }
+
+        // confirmed ticks never have to be simulated again
+        let oldest = session.confirmed.min(session.tick);
+        simulation.confirm(oldest);
+        while self
+            .snapshots
+            .front()
+            .is_some_and(|(tick, _)| *tick < oldest)
+        {
+            self.snapshots.pop_front();
+        }
+        self.history = self.history.split_off(&oldest.saturating_sub(1));
+        session.forget(oldest);
+        !waiting
+    }
+
+    fn step(&mut self, session: &mut NetSession, simulation: &mut impl Simulate<Snapshot = S>) {
+        let tick = session.tick;
+        self.snapshots.push_back((tick, simulation.save()));
+        let inputs = session.inputs(tick);
+        let previous = tick
+            .checked_sub(1)
+            .and_then(|before| self.history.get(&before))
+            .copied()
+            .unwrap_or_default();
+        simulation.step(tick, previous, inputs, tick >= session.confirmed);
+        self.history.insert(tick, inputs);
+        session.tick += 1;
+    }
+}
+
+/// The game in the world, as far as rollback is concerned
+pub struct WorldSimulation<'a> {
+    pub world: &'a mut World,
+    pub simulation: &'a mut Simulation,
+}
+
+impl Simulate for WorldSimulation<'_> {
+    type Snapshot = GameSnapshot;
+
+    fn save(&mut self) -> GameSnapshot {
+        self.simulation.save(self.world)
+    }
+
+    fn load(&mut self, snapshot: &GameSnapshot) {
+        self.simulation.load(self.world, snapshot);
+    }
+
+    fn step(&mut self, tick: u64, previous: [u8; PLAYERS], inputs: [u8; PLAYERS], predicted: bool) {
+        let mut actions = PlayerActions::default();
+        for (player, input) in actions.0.iter_mut().enumerate() {
+            apply_bits(input, previous[player]);
+            apply_bits(input, inputs[player]);
+        }
+        self.world.insert_resource(actions);
+        self.world.insert_resource(PredictedInputs(predicted));
+        self.world
+            .resource_mut::<Effects>()
+            .start_tick(tick, predicted);
+        self.simulation.step(self.world);
+        self.world.resource_mut::<Effects>().finish_tick();
+        self.world.insert_resource(PredictedInputs(false));
+    }
+
+    fn can_predict(&self) -> bool {
+        self.simulation.can_predict(self.world)
+    }
+
+    fn confirm(&mut self, confirmed: u64) {
+        self.world.resource_mut::<Effects>().confirm(confirmed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::link::SimulatedLink;
+    use super::*;
+    use crate::game_plugin::{Level, Position, Score};
+    use crate::headless_plugin::harness::Harness;
+    use crate::settings::Action;
+
+    /// Folds every input into a number, so that any difference in the inputs or
+    /// their order shows
+    #[derive(Default)]
+    struct Checksum {
+        tick: u64,
+        value: u64,
+        /// The value after every tick
+        values: BTreeMap<u64, u64>,
+        loads: usize,
+    }
+
+    impl Simulate for Checksum {
+        type Snapshot = (u64, u64);
+
+        fn save(&mut self) -> (u64, u64) {
+            (self.tick, self.value)
+        }
+
+        fn load(&mut self, snapshot: &(u64, u64)) {
+            (self.tick, self.value) = *snapshot;
+            self.loads += 1;
+        }
+
+        fn step(&mut self, _: u64, previous: [u8; PLAYERS], inputs: [u8; PLAYERS], _: bool) {
+            for (before, now) in previous.iter().zip(inputs) {
+                self.value = self.value.wrapping_mul(31).wrapping_add(*before as u64);
+                self.value = self.value.wrapping_mul(31).wrapping_add(now as u64);
+            }
+            self.values.insert(self.tick, self.value);
+            self.tick += 1;
+        }
+
+        fn can_predict(&self) -> bool {
+            true
+        }
+    }
+
+    /// Inputs that change every few frames, differently for both players
+    fn scripted_bits(frame: u64, player: usize) -> u8 {
+        ((frame / (5 + player as u64 * 2)) * 37 % 32) as u8
+    }
+
+    #[test]
+    fn test_rollback_over_bad_link() {
+        let (a, b, clock) = SimulatedLink::pair(4, 3, 20);
+        let mut sessions = [
+            NetSession::new(Box::new(a), 0, false),
+            NetSession::new(Box::new(b), 1, false),
+        ];
+        let mut rollbacks: [Rollback<(u64, u64)>; 2] = default();
+        let mut checksums: [Checksum; 2] = default();
+        for frame in 0..600 {
+            for player in 0..PLAYERS {
+                let session = &mut sessions[player];
+                // both players stop pressing anything at the end
+                let bits = if frame < 500 {
+                    scripted_bits(frame, player)
+                } else {
+                    0
+                };
+                session.schedule(bits);
+                session.send_inputs();
+                session.receive();
+                rollbacks[player].advance(session, &mut checksums[player]);
+            }
+            clock.advance();
+        }
+
+        let confirmed = sessions
+            .iter()
+            .map(|session| session.confirmed.min(session.tick))
+            .min()
+            .unwrap();
+        assert!(confirmed > 500, "only {confirmed} ticks were confirmed");
+        assert!(checksums.iter().all(|checksum| checksum.loads > 0));
+        for tick in 0..confirmed {
+            assert_eq!(
+                checksums[0].values[&tick], checksums[1].values[&tick],
+                "diverged in tick {tick}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_waits_for_lost_link() {
+        let (a, _b, _) = SimulatedLink::pair(1, 0, 100);
+        let mut session = NetSession::new(Box::new(a), 0, false);
+        let mut rollback = Rollback::default();
+        let mut checksum = Checksum::default();
+        let mut steps = 0;
+        for _ in 0..50 {
+            session.schedule(1);
+            if rollback.advance(&mut session, &mut checksum) {
+                steps += 1;
+            }
+        }
+        // the remote inputs of the first ticks are known without asking
+        assert_eq!(steps, session.confirmed + MAX_PREDICTION);
+        assert_eq!(checksum.tick, steps);
+    }
+
+    /// What two runs of the same ticks have to agree on
+    fn game_state(world: &World) -> String {
+        let level = world.resource::<Level>();
+        let score = world.resource::<Score>();
+        let sorted = |mut positions: Vec<Position>| {
+            positions.sort_by_key(|position| (position.x, position.z));
+            positions
+        };
+        let players: Vec<_> = (0..PLAYERS)
+            .map(|player| level.player_positions.get(&player).copied())
+            .collect();
+        let enemies = sorted(level.enemy_positions().values().copied().collect());
+        let coins = sorted(level.coin_positions().values().copied().collect());
+        let bombs = sorted(level.bombs().values().map(|(_, n)| *n).collect());
+        format!(
+            "{players:?} {enemies:?} {coins:?} {bombs:?} {} {} {}",
+            score.points, score.bombs, level.stats.moves
+        )
+    }
+
+    /// Walks right, places a bomb on the way and turns down at the end
+    fn game_bits(tick: u64) -> [u8; PLAYERS] {
+        let bit = |action| 1 << Action::PLAYED.iter().position(|n| *n == action).unwrap();
+        let bits = match tick {
+            40 => bit(Action::PlaceBomb),
+            0..=79 => bit(Action::MoveRight),
+            _ => bit(Action::MoveDown),
+        };
+        [bits, 0]
+    }
+
+    #[test]
+    fn test_rollback_of_the_game() {
+        let mut harness = Harness::new(
+            "
+#########
+#o   *  #
+####### #
+#   *  e#
+#########
+",
+        );
+        harness
+            .app
+            .world
+            .resource_scope(|world, mut simulation: Mut<Simulation>| {
+                let mut game = WorldSimulation {
+                    world,
+                    simulation: &mut simulation,
+                };
+                let step = |game: &mut WorldSimulation, tick: u64| {
+                    let previous = tick.checked_sub(1).map(game_bits).unwrap_or_default();
+                    game.step(tick, previous, game_bits(tick), false);
+                    game_state(game.world)
+                };
+                for tick in 0..50 {
+                    step(&mut game, tick);
+                }
+                // the bomb is still ticking in the snapshot
+                let snapshot = game.save();
+                assert_eq!(game.world.resource::<Level>().bombs().len(), 1);
+                let states: Vec<_> = (50..200).map(|tick| step(&mut game, tick)).collect();
+                game.load(&snapshot);
+                for (tick, state) in (50..200).zip(states) {
+                    assert_eq!(step(&mut game, tick), state, "diverged in tick {tick}");
+                }
+            });
+    }
+}
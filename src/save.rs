@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fs;
+
+const SAVE_FILE: &str = "pacbomber_save.ron";
+
+/// Missing or broken files are treated as if nothing was stored
+pub fn read<T: DeserializeOwned>(path: &str) -> Option<T> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| ron::from_str(&data).ok())
+}
+
+pub fn write<T: Serialize>(path: &str, value: &T) {
+    let data = match ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Could not serialize {path}: {e}");
+            return;
+        }
+    };
+    if let Err(e) = fs::write(path, data) {
+        warn!("Could not write {path}: {e}");
+    }
+}
+
+/// Progress that is kept between runs of the game
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    /// The best amount of stars reached, per level
+    pub stars: Vec<usize>,
+    /// Whether a level was finished at least once
+    pub cleared: Vec<bool>,
+}
+
+impl SaveData {
+    pub fn load() -> Self {
+        read(SAVE_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        write(SAVE_FILE, self);
+    }
+
+    pub fn stars(&self, level: usize) -> usize {
+        self.stars.get(level).copied().unwrap_or(0)
+    }
+
+    /// The first level is always open, every other one once its predecessor was cleared
+    pub fn is_unlocked(&self, level: usize) -> bool {
+        level == 0 || self.cleared.get(level - 1).copied().unwrap_or(false)
+    }
+
+    /// Marks the level as cleared and only keeps the best amount of stars
+    pub fn record_completion(&mut self, level: usize, stars: usize) {
+        if self.stars.len() <= level {
+            self.stars.resize(level + 1, 0);
+        }
+        if self.cleared.len() <= level {
+            self.cleared.resize(level + 1, false);
+        }
+        self.stars[level] = self.stars[level].max(stars);
+        self.cleared[level] = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlock_progression() {
+        let mut save = SaveData::default();
+        assert!(save.is_unlocked(0));
+        assert!(!save.is_unlocked(1));
+        save.record_completion(0, 3);
+        save.record_completion(2, 1);
+        save.record_completion(0, 1);
+        assert_eq!(save.stars, vec![3, 0, 1]);
+        assert!(save.is_unlocked(1));
+        assert!(!save.is_unlocked(2));
+        assert!(save.is_unlocked(3));
+    }
+}
@@ -0,0 +1,373 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::save;
+
+const SETTINGS_FILE: &str = "pacbomber_settings.ron";
+
+/// How many players can share one game
+pub const PLAYERS: usize = 2;
+
+/// Everything the player can do, independent of the key that does it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    PlaceBomb,
+    Pause,
+    Confirm,
+    /// Leaves a screen or the current run to the menu
+    Back,
+    /// Switches the window to fullscreen and back
+    Fullscreen,
+    /// Switches to the top-down view and back
+    TopDown,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::PlaceBomb,
+        Action::Pause,
+        Action::Confirm,
+        Action::Back,
+        Action::Fullscreen,
+        Action::TopDown,
+    ];
+
+    /// The actions that are sent to the other instance of an online match, the
+    /// rest only changes how this one shows the game
+    pub const PLAYED: [Action; 8] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::PlaceBomb,
+        Action::Pause,
+        Action::Confirm,
+        Action::Back,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move Up",
+            Action::MoveDown => "Move Down",
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::PlaceBomb => "Place Bomb",
+            Action::Pause => "Pause",
+            Action::Confirm => "Confirm",
+            Action::Back => "Back",
+            Action::Fullscreen => "Fullscreen",
+            Action::TopDown => "Top-Down View",
+        }
+    }
+
+    /// The first player plays on the left of the keyboard and the second one
+    /// on the right, so that no key belongs to both
+    fn default_keys(&self, player: usize) -> Vec<KeyCode> {
+        match (player, self) {
+            (0, Action::MoveUp) => vec![KeyCode::W],
+            (0, Action::MoveDown) => vec![KeyCode::S],
+            (0, Action::MoveLeft) => vec![KeyCode::A],
+            (0, Action::MoveRight) => vec![KeyCode::D],
+            (0, Action::PlaceBomb) => vec![KeyCode::Space],
+            (0, Action::Pause) => vec![KeyCode::P],
+            (0, Action::Confirm) => vec![KeyCode::Return],
+            (0, Action::Back) => vec![KeyCode::Escape],
+            (0, Action::Fullscreen) => vec![KeyCode::F11],
+            (0, Action::TopDown) => vec![KeyCode::F10],
+            (_, Action::MoveUp) => vec![KeyCode::Up],
+            (_, Action::MoveDown) => vec![KeyCode::Down],
+            (_, Action::MoveLeft) => vec![KeyCode::Left],
+            (_, Action::MoveRight) => vec![KeyCode::Right],
+            (_, Action::PlaceBomb) => vec![KeyCode::RShift],
+            // the menus and the view are left to the first player
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBindings(BTreeMap<Action, Vec<KeyCode>>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::for_player(0)
+    }
+}
+
+impl KeyBindings {
+    pub fn for_player(player: usize) -> Self {
+        KeyBindings(
+            Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_keys(player)))
+                .collect(),
+        )
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        self.keys(action).iter().any(|key| input.pressed(*key))
+    }
+
+    pub fn just_pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        self.keys(action).iter().any(|key| input.just_pressed(*key))
+    }
+
+    /// Replaces all keys of the action with the given one
+    fn bind(&mut self, action: Action, key: KeyCode) {
+        self.0.insert(action, vec![key]);
+    }
+
+    /// A human readable list of the keys of an action
+    pub fn describe(&self, action: Action) -> String {
+        if self.keys(action).is_empty() {
+            return "None".to_string();
+        }
+        self.keys(action)
+            .iter()
+            .map(|key| format!("{key:?}"))
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+/// Colours that replace some of the level's theme, for players who can't
+/// tell its colours apart
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Theme,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+    HighContrast,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 5] = [
+        Palette::Theme,
+        Palette::Deuteranopia,
+        Palette::Protanopia,
+        Palette::Tritanopia,
+        Palette::HighContrast,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Palette::Theme => "Level Colours",
+            Palette::Deuteranopia => "Deuteranopia",
+            Palette::Protanopia => "Protanopia",
+            Palette::Tritanopia => "Tritanopia",
+            Palette::HighContrast => "High Contrast",
+        }
+    }
+
+    /// The name in `assets/palettes.ron`
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Palette::Theme => None,
+            Palette::Deuteranopia => Some("deuteranopia"),
+            Palette::Protanopia => Some("protanopia"),
+            Palette::Tritanopia => Some("tritanopia"),
+            Palette::HighContrast => Some("high_contrast"),
+        }
+    }
+
+    pub fn next(&self) -> Palette {
+        let index = Palette::ALL.iter().position(|n| n == self).unwrap_or(0);
+        Palette::ALL[(index + 1) % Palette::ALL.len()]
+    }
+}
+
+/// How many particles explosions, coins, deaths and the exit throw
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParticleQuality {
+    Off,
+    Low,
+    #[default]
+    High,
+}
+
+impl ParticleQuality {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ParticleQuality::Off => "Particles: Off",
+            ParticleQuality::Low => "Particles: Low",
+            ParticleQuality::High => "Particles: High",
+        }
+    }
+
+    /// How many of the particles of a burst are spawned
+    pub fn count(&self, particles: usize) -> usize {
+        match self {
+            ParticleQuality::Off => 0,
+            ParticleQuality::Low => particles.div_ceil(3),
+            ParticleQuality::High => particles,
+        }
+    }
+
+    pub fn next(&self) -> ParticleQuality {
+        match self {
+            ParticleQuality::Off => ParticleQuality::Low,
+            ParticleQuality::Low => ParticleQuality::High,
+            ParticleQuality::High => ParticleQuality::Off,
+        }
+    }
+}
+
+/// Player preferences, stored in their own file next to the save data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub bindings: KeyBindings,
+    /// The keys of the second player when two share the keyboard
+    pub second_bindings: KeyBindings,
+    /// How far the gamepad stick has to be pushed to move
+    pub stick_deadzone: f32,
+    /// Toggled with `Action::Fullscreen`
+    pub fullscreen: bool,
+    /// The board seen straight from above with flat colours, toggled with
+    /// `Action::TopDown`
+    pub top_down: bool,
+    pub palette: Palette,
+    /// Explosions are drawn as crosses, so that they aren't told apart from
+    /// coins by their colour alone
+    pub shape_cues: bool,
+    pub particles: ParticleQuality,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            bindings: KeyBindings::default(),
+            second_bindings: KeyBindings::for_player(1),
+            stick_deadzone: 0.5,
+            fullscreen: false,
+            top_down: false,
+            palette: Palette::Theme,
+            shape_cues: false,
+            particles: ParticleQuality::High,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let mut settings: Settings = save::read(SETTINGS_FILE).unwrap_or_default();
+        // actions added after the file was written get their default keys
+        for player in 0..PLAYERS {
+            for action in Action::ALL {
+                settings
+                    .bindings_mut(player)
+                    .0
+                    .entry(action)
+                    .or_insert_with(|| action.default_keys(player));
+            }
+        }
+        settings
+    }
+
+    pub fn bindings(&self, player: usize) -> &KeyBindings {
+        match player {
+            0 => &self.bindings,
+            _ => &self.second_bindings,
+        }
+    }
+
+    pub fn bindings_mut(&mut self, player: usize) -> &mut KeyBindings {
+        match player {
+            0 => &mut self.bindings,
+            _ => &mut self.second_bindings,
+        }
+    }
+
+    /// Binds the key to the action of the player and takes it away from every
+    /// other action of both players, so that a key never does two things. An
+    /// action that would be left without a key gets the previous keys of the
+    /// rebound one instead. Returns false, and changes nothing, if there are no
+    /// previous keys to give it
+    pub fn bind(&mut self, player: usize, action: Action, key: KeyCode) -> bool {
+        let previous = self.bindings(player).keys(action).to_vec();
+        let mut changed = self.clone();
+        for other in 0..PLAYERS {
+            for (other_action, keys) in changed.bindings_mut(other).0.iter_mut() {
+                if (other, *other_action) == (player, action) || !keys.contains(&key) {
+                    continue;
+                }
+                keys.retain(|n| *n != key);
+                if keys.is_empty() {
+                    if previous.is_empty() {
+                        return false;
+                    }
+                    keys.clone_from(&previous);
+                }
+            }
+        }
+        changed.bindings_mut(player).bind(action, key);
+        *self = changed;
+        true
+    }
+
+    pub fn save(&self) {
+        save::write(SETTINGS_FILE, self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_takes_the_key_from_other_actions() {
+        let mut settings = Settings::default();
+        assert!(settings.bind(0, Action::MoveDown, KeyCode::W));
+        assert_eq!(settings.bindings(0).keys(Action::MoveDown), &[KeyCode::W]);
+        assert_eq!(settings.bindings(0).keys(Action::MoveUp), &[KeyCode::S]);
+
+        // space places the bombs of the first player, who gets the old key of
+        // the second one instead of none at all
+        assert!(settings.bind(1, Action::PlaceBomb, KeyCode::Space));
+        assert_eq!(
+            settings.bindings(1).keys(Action::PlaceBomb),
+            &[KeyCode::Space]
+        );
+        assert_eq!(
+            settings.bindings(0).keys(Action::PlaceBomb),
+            &[KeyCode::RShift]
+        );
+    }
+
+    #[test]
+    fn test_bind_never_leaves_an_action_without_keys() {
+        let mut settings = Settings::default();
+        settings.bind(0, Action::Confirm, KeyCode::Space);
+        assert_eq!(
+            settings.bindings(0).keys(Action::Confirm),
+            &[KeyCode::Space]
+        );
+        assert_eq!(
+            settings.bindings(0).keys(Action::PlaceBomb),
+            &[KeyCode::Return]
+        );
+
+        // the second player has no key to give away for pausing
+        assert!(!settings.bind(1, Action::Pause, KeyCode::Space));
+        assert!(settings.bindings(1).keys(Action::Pause).is_empty());
+        assert_eq!(
+            settings.bindings(0).keys(Action::Confirm),
+            &[KeyCode::Space]
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use crate::{
+    actions_plugin::Navigable,
+    settings::{Action, KeyBindings, Settings, PLAYERS},
+    GameState,
+};
+use bevy::prelude::*;
+
+pub struct SettingsPlugin;
+
+#[derive(Component)]
+struct LocalEntity;
+
+#[derive(Component, Copy, Clone, PartialEq, Eq)]
+enum SettingsButton {
+    /// The player and the action to rebind
+    Rebind(usize, Action),
+    Fullscreen,
+    TopDown,
+    Palette,
+    ShapeCues,
+    Particles,
+    Defaults,
+    Back,
+}
+
+/// The player and action that wait for a new key
+#[derive(Default)]
+struct Rebinding(Option<(usize, Action)>);
+
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rebinding>()
+            .add_system_set(SystemSet::on_enter(GameState::Settings).with_system(setup))
+            .add_system_set(SystemSet::on_exit(GameState::Settings).with_system(exit))
+            .add_system_set(
+                SystemSet::on_update(GameState::Settings)
+                    .with_system(keyboard_input_system)
+                    // a rebinding started with a key only waits from the next frame on
+                    .with_system(button_system.after(keyboard_input_system))
+                    .with_system(update_labels),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/Archivo-Bold.ttf");
+    let text_style = |font_size: f32, color: Color| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Settings",
+                text_style(50.0, Color::rgb(1.0, 1.0, 0.0)),
+            ));
+            let row = || NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(560.0), Val::Auto),
+                    justify_content: JustifyContent::SpaceBetween,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                color: Color::NONE.into(),
+                ..default()
+            };
+            parent.spawn_bundle(row()).with_children(|parent| {
+                for label in ["", "Player 1", "Player 2"] {
+                    parent.spawn_bundle(
+                        TextBundle::from_section(
+                            label,
+                            text_style(20.0, Color::rgb(0.0, 0.9, 0.9)),
+                        )
+                        .with_style(Style {
+                            size: Size::new(Val::Px(180.0), Val::Auto),
+                            ..default()
+                        }),
+                    );
+                }
+            });
+            // one row per action, with a column per player
+            for action in Action::ALL {
+                parent.spawn_bundle(row()).with_children(|parent| {
+                    parent.spawn_bundle(
+                        TextBundle::from_section(
+                            action.label(),
+                            text_style(20.0, Color::rgb(0.9, 0.9, 0.9)),
+                        )
+                        .with_style(Style {
+                            size: Size::new(Val::Px(180.0), Val::Auto),
+                            ..default()
+                        }),
+                    );
+                    for player in 0..PLAYERS {
+                        spawn_button(
+                            parent,
+                            text_style(20.0, Color::rgb(0.9, 0.9, 0.9)),
+                            SettingsButton::Rebind(player, action),
+                            180.0,
+                        );
+                    }
+                });
+            }
+            // how the game looks, then leaving the screen
+            let rows = [
+                vec![
+                    SettingsButton::Fullscreen,
+                    SettingsButton::TopDown,
+                    SettingsButton::Palette,
+                    SettingsButton::ShapeCues,
+                    SettingsButton::Particles,
+                ],
+                vec![SettingsButton::Defaults, SettingsButton::Back],
+            ];
+            for (index, buttons) in rows.into_iter().enumerate() {
+                let top = if index == 0 { 20.0 } else { 0.0 };
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            margin: UiRect::new(
+                                Val::Px(0.0),
+                                Val::Px(0.0),
+                                Val::Px(top),
+                                Val::Px(0.0),
+                            ),
+                            ..default()
+                        },
+                        color: Color::NONE.into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        // narrower, so that the whole row fits into the window
+                        for button in buttons {
+                            spawn_button(
+                                parent,
+                                text_style(20.0, Color::rgb(0.9, 0.9, 0.9)),
+                                button,
+                                160.0,
+                            );
+                        }
+                    });
+            }
+        })
+        .insert(LocalEntity);
+}
+
+fn spawn_button(parent: &mut ChildBuilder, style: TextStyle, button: SettingsButton, width: f32) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(40.0)),
+                margin: UiRect::all(Val::Px(5.0)),
+                // horizontally center child text
+                justify_content: JustifyContent::Center,
+                // vertically center child text
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section("", style));
+        })
+        .insert(button)
+        .insert(Navigable {
+            color: NORMAL_BUTTON,
+        });
+}
+
+fn exit(
+    mut commands: Commands,
+    destroy_query: Query<Entity, With<LocalEntity>>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    for entity in destroy_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    rebinding.0 = None;
+}
+
+/// Assigns the next pressed key to the action that is being rebound
+fn keyboard_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
+    mut app_state: ResMut<State<GameState>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    let key = keyboard_input.get_just_pressed().next().copied();
+    let back_key = key.is_some_and(|key| {
+        (0..PLAYERS).any(|player| settings.bindings(player).keys(Action::Back).contains(&key))
+    });
+    let back = back_key || actions.just_pressed(Action::Back);
+    match (rebinding.0, key) {
+        // going back, with a key or on a gamepad, cancels the rebinding
+        (Some(_), _) if back => rebinding.0 = None,
+        (Some((player, action)), Some(key)) => {
+            rebinding.0 = None;
+            // a key that would leave another action without one is ignored
+            if settings.bind(player, action, key) {
+                settings.save();
+            }
+        }
+        (None, _) if back => app_state.set(GameState::Menu).unwrap(),
+        _ => {}
+    }
+}
+
+#[allow(clippy::complexity)]
+fn button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &SettingsButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut app_state: ResMut<State<GameState>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Clicked => {
+                *color = PRESSED_BUTTON.into();
+                match button {
+                    SettingsButton::Rebind(player, action) => {
+                        rebinding.0 = Some((*player, *action))
+                    }
+                    SettingsButton::Fullscreen => {
+                        settings.fullscreen = !settings.fullscreen;
+                        settings.save();
+                    }
+                    SettingsButton::TopDown => {
+                        settings.top_down = !settings.top_down;
+                        settings.save();
+                    }
+                    SettingsButton::Palette => {
+                        settings.palette = settings.palette.next();
+                        settings.save();
+                    }
+                    SettingsButton::ShapeCues => {
+                        settings.shape_cues = !settings.shape_cues;
+                        settings.save();
+                    }
+                    SettingsButton::Particles => {
+                        settings.particles = settings.particles.next();
+                        settings.save();
+                    }
+                    SettingsButton::Defaults => {
+                        for player in 0..PLAYERS {
+                            *settings.bindings_mut(player) = KeyBindings::for_player(player);
+                        }
+                        settings.save();
+                    }
+                    SettingsButton::Back => app_state.set(GameState::Menu).unwrap(),
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+fn update_labels(
+    buttons: Query<(&SettingsButton, &Children)>,
+    added: Query<(), Added<SettingsButton>>,
+    mut text_query: Query<&mut Text>,
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+) {
+    if !settings.is_changed() && !rebinding.is_changed() && added.is_empty() {
+        return;
+    }
+    for (button, children) in buttons.iter() {
+        let mut text = text_query.get_mut(children[0]).unwrap();
+        text.sections[0].value = match button {
+            SettingsButton::Rebind(player, action) if rebinding.0 == Some((*player, *action)) => {
+                "Press a key".to_string()
+            }
+            SettingsButton::Rebind(player, action) => settings.bindings(*player).describe(*action),
+            SettingsButton::Fullscreen if settings.fullscreen => "Windowed".to_string(),
+            SettingsButton::Fullscreen => "Fullscreen".to_string(),
+            SettingsButton::TopDown if settings.top_down => "3D View".to_string(),
+            SettingsButton::TopDown => "Top-Down".to_string(),
+            SettingsButton::Palette => settings.palette.label().to_string(),
+            SettingsButton::ShapeCues if settings.shape_cues => "Shapes: On".to_string(),
+            SettingsButton::ShapeCues => "Shapes: Off".to_string(),
+            SettingsButton::Particles => settings.particles.label().to_string(),
+            SettingsButton::Defaults => "Defaults".to_string(),
+            SettingsButton::Back => "Back".to_string(),
+        };
+    }
+}
@@ -0,0 +1,224 @@
+use bevy::prelude::*;
+use serde::Serialize;
+use std::{
+    env,
+    io::Write,
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{
+    game_plugin::{LevelView, Observation, Observer},
+    GameState,
+};
+
+/// How many messages may wait for a slow viewer before it is dropped
+const QUEUE: usize = 120;
+/// Viewers beyond this are turned away, every one of them costs a thread
+const MAX_VIEWERS: usize = 16;
+
+/// Streams the running game to viewers on a local TCP port, given on the
+/// command line as `--spectate <address>`. Every message is one line of JSON,
+/// see the README for the format
+pub struct SpectatePlugin;
+
+/// The messages viewers receive, tagged with their `type`
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message<'a> {
+    /// Sent when a level starts, and to new viewers before their first tick
+    Level(&'a LevelView),
+    Tick {
+        tick: u64,
+        #[serde(flatten)]
+        state: &'a Observation,
+    },
+}
+
+impl Message<'_> {
+    fn line(&self) -> Arc<str> {
+        let mut line = serde_json::to_string(self).expect("messages are valid JSON");
+        line.push('\n');
+        line.into()
+    }
+}
+
+struct Viewer {
+    sender: SyncSender<Arc<str>>,
+    knows_level: bool,
+}
+
+struct Spectators {
+    listener: TcpListener,
+    viewers: Vec<Viewer>,
+    tick: u64,
+    /// The message describing the current level
+    level: Option<Arc<str>>,
+}
+
+impl Spectators {
+    fn from_args() -> Option<Self> {
+        let args: Vec<String> = env::args().collect();
+        let address = args
+            .windows(2)
+            .find(|pair| pair[0] == "--spectate")
+            .map(|pair| pair[1].clone())?;
+        let listener = match TcpListener::bind(&address).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        }) {
+            Ok(n) => n,
+            Err(e) => {
+                warn!("Could not stream to spectators on {address}: {e}");
+                return None;
+            }
+        };
+        info!("Streaming to spectators on {address}");
+        Some(Self {
+            listener,
+            viewers: Vec::new(),
+            tick: 0,
+            level: None,
+        })
+    }
+
+    /// Every viewer gets its own thread, so that the game never waits for one
+    fn accept(&mut self) {
+        while let Ok((stream, address)) = self.listener.accept() {
+            if self.viewers.len() >= MAX_VIEWERS {
+                info!("Turned spectator {address} away, there are too many");
+                continue;
+            }
+            if let Err(e) = stream.set_nonblocking(false) {
+                debug!("Could not stream to {address}: {e}");
+                continue;
+            }
+            let (sender, receiver) = mpsc::sync_channel(QUEUE);
+            thread::spawn(move || write_lines(stream, receiver.iter()));
+            info!("Spectator {address} joined");
+            self.viewers.push(Viewer {
+                sender,
+                knows_level: false,
+            });
+        }
+    }
+
+    /// Drops the viewers that left or can't keep up
+    fn send(&mut self, tick: &Arc<str>) {
+        let level = self.level.clone();
+        self.viewers.retain_mut(|viewer| {
+            let mut lines = Vec::new();
+            if !viewer.knows_level {
+                lines.extend(level.clone());
+                viewer.knows_level = true;
+            }
+            lines.push(tick.clone());
+            lines
+                .into_iter()
+                .all(|line| viewer.sender.try_send(line).is_ok())
+        });
+    }
+}
+
+fn write_lines(mut stream: TcpStream, lines: impl Iterator<Item = Arc<str>>) {
+    for line in lines {
+        if stream.write_all(line.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+impl Plugin for SpectatePlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(spectators) = Spectators::from_args() {
+            app.insert_resource(spectators)
+                .add_system(accept_system)
+                .add_system_set(
+                    SystemSet::on_update(GameState::Running).with_system(stream_system),
+                );
+        }
+    }
+}
+
+fn accept_system(mut spectators: ResMut<Spectators>) {
+    spectators.accept();
+}
+
+fn stream_system(mut spectators: ResMut<Spectators>, observer: Observer) {
+    if observer.level_changed() || spectators.level.is_none() {
+        spectators.level = Some(Message::Level(&observer.describe_level()).line());
+        for viewer in spectators.viewers.iter_mut() {
+            viewer.knows_level = false;
+        }
+    }
+    let tick = spectators.tick;
+    spectators.tick += 1;
+    let line = Message::Tick {
+        tick,
+        state: &observer.observe(),
+    }
+    .line();
+    spectators.send(&line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_plugin::GameMode;
+
+    #[test]
+    fn test_message_format() {
+        let level = LevelView {
+            mode: GameMode::Versus,
+            level: 1,
+            round: Some(2),
+            rows: vec!["#e#".into()],
+        };
+        assert_eq!(
+            &*Message::Level(&level).line(),
+            "{\"type\":\"level\",\"mode\":\"Versus\",\"level\":1,\"round\":2,\"rows\":[\"#e#\"]}\n"
+        );
+        let state = Observation {
+            players: Vec::new(),
+            enemies: Vec::new(),
+            coins: Vec::new(),
+            bombs: Vec::new(),
+            explosions: Vec::new(),
+            exit: None,
+            scores: Vec::new(),
+            finished: false,
+        };
+        let line = Message::Tick {
+            tick: 7,
+            state: &state,
+        }
+        .line();
+        assert!(line.starts_with("{\"type\":\"tick\",\"tick\":7,\"players\":[]"));
+    }
+
+    #[test]
+    fn test_viewers_are_limited() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut spectators = Spectators {
+            listener,
+            viewers: Vec::new(),
+            tick: 0,
+            level: None,
+        };
+        let _streams: Vec<_> = (0..MAX_VIEWERS + 2)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+        // connections can take a moment to show up on the listener
+        for _ in 0..100 {
+            spectators.accept();
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(spectators.viewers.len(), MAX_VIEWERS);
+    }
+}
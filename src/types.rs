@@ -3,7 +3,8 @@ pub struct MaterialHandles {
     pub wall_normal: Handle<StandardMaterial>,
     pub wall_hidden: Handle<StandardMaterial>,
     pub coin: Handle<StandardMaterial>,
-    pub player: Handle<StandardMaterial>,
+    /// One per player index
+    pub players: Vec<Handle<StandardMaterial>>,
     pub enemy: Handle<StandardMaterial>,
     pub floor_bg: Handle<StandardMaterial>,
     pub floor_fg: Handle<StandardMaterial>,
@@ -13,6 +14,31 @@ pub struct MaterialHandles {
     pub white: Handle<StandardMaterial>,
 }
 
+impl MaterialHandles {
+    pub fn all(&self) -> impl Iterator<Item = &Handle<StandardMaterial>> {
+        [
+            &self.wall_normal,
+            &self.wall_hidden,
+            &self.coin,
+            &self.enemy,
+            &self.floor_bg,
+            &self.floor_fg,
+            &self.ground,
+            &self.bomb,
+            &self.explosion,
+            &self.white,
+        ]
+        .into_iter()
+        .chain(self.players.iter())
+    }
+}
+
+/// A light above the level, with its intensity before the theme scales it
+#[derive(Component)]
+pub struct SceneLight {
+    pub intensity: f32,
+}
+
 pub struct MeshHandles {
     pub wall: Handle<Mesh>,
     pub wall_h: Handle<Mesh>,
@@ -22,6 +48,9 @@ pub struct MeshHandles {
     pub enemy_eye: Handle<Mesh>,
     pub floor_fg: Handle<Mesh>,
     pub floor_bg: Handle<Mesh>,
+    /// Explosions while `Settings::shape_cues` is on
+    pub explosion_cue: Handle<Mesh>,
+    pub particle: Handle<Mesh>,
     pub floor_cube: Handle<Mesh>,
 }
 
@@ -0,0 +1,168 @@
+use bevy::{prelude::*, window::WindowMode};
+
+use crate::settings::{Action, Settings};
+
+/// The window size the UI was laid out for. Sizes in pixels and fonts are
+/// scaled from it, so that the UI keeps its proportions in any window
+pub const DESIGN_SIZE: Vec2 = Vec2::new(900.0, 660.0);
+
+/// The UI never shrinks below this, a minimized window has no size at all
+const MIN_UI_SCALE: f32 = 0.25;
+
+/// Resizing, fullscreen, the top-down view and the scale of the UI
+pub struct WindowPlugin;
+
+impl Plugin for WindowPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(toggle_view)
+            .add_system(apply_window_mode.after(toggle_view))
+            .add_system_to_stage(CoreStage::PostUpdate, scale_ui);
+    }
+}
+
+pub fn window_mode(fullscreen: bool) -> WindowMode {
+    if fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    }
+}
+
+/// `Action::Fullscreen` switches to fullscreen and back, `Action::TopDown` to
+/// the top-down view and back
+fn toggle_view(actions: Res<Input<Action>>, mut settings: ResMut<Settings>) {
+    if actions.just_pressed(Action::Fullscreen) {
+        settings.fullscreen = !settings.fullscreen;
+        settings.save();
+    }
+    if actions.just_pressed(Action::TopDown) {
+        settings.top_down = !settings.top_down;
+        settings.save();
+    }
+}
+
+/// Follows the setting, which can also change in the settings screen
+fn apply_window_mode(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+    let mode = window_mode(settings.fullscreen);
+    if let Some(window) = windows.get_primary_mut() {
+        if window.mode() != mode {
+            window.set_mode(mode);
+        }
+    }
+}
+
+/// How much larger the UI is than at `DESIGN_SIZE`
+fn ui_scale(width: f32, height: f32) -> f32 {
+    (width / DESIGN_SIZE.x)
+        .min(height / DESIGN_SIZE.y)
+        .max(MIN_UI_SCALE)
+}
+
+/// The sizes of a UI node at `DESIGN_SIZE`
+#[derive(Component)]
+struct Unscaled {
+    style: Style,
+    fonts: Vec<f32>,
+}
+
+fn scale_val(value: Val, scale: f32) -> Val {
+    match value {
+        Val::Px(n) => Val::Px(n * scale),
+        other => other,
+    }
+}
+
+fn scale_rect(rect: UiRect<Val>, scale: f32) -> UiRect<Val> {
+    UiRect {
+        left: scale_val(rect.left, scale),
+        right: scale_val(rect.right, scale),
+        top: scale_val(rect.top, scale),
+        bottom: scale_val(rect.bottom, scale),
+    }
+}
+
+fn scale_size(size: Size<Val>, scale: f32) -> Size<Val> {
+    Size::new(scale_val(size.width, scale), scale_val(size.height, scale))
+}
+
+/// Only touches the sizes, everything else stays as the game set it
+fn scale_style(style: &mut Style, unscaled: &Style, scale: f32) {
+    style.size = scale_size(unscaled.size, scale);
+    style.min_size = scale_size(unscaled.min_size, scale);
+    style.max_size = scale_size(unscaled.max_size, scale);
+    style.position = scale_rect(unscaled.position, scale);
+    style.margin = scale_rect(unscaled.margin, scale);
+    style.padding = scale_rect(unscaled.padding, scale);
+    style.border = scale_rect(unscaled.border, scale);
+}
+
+fn scale_text(text: &mut Text, fonts: &[f32], scale: f32) {
+    for (section, font_size) in text.sections.iter_mut().zip(fonts) {
+        section.style.font_size = font_size * scale;
+    }
+}
+
+/// Scales new UI nodes, and all of them when the window changed its size
+fn scale_ui(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    mut current: Local<Option<f32>>,
+    mut nodes: Query<(Entity, &mut Style, Option<&mut Text>, Option<&Unscaled>)>,
+) {
+    let scale = match windows.get_primary() {
+        Some(window) => ui_scale(window.width(), window.height()),
+        None => return,
+    };
+    let resized = *current != Some(scale);
+    *current = Some(scale);
+    for (entity, mut style, text, unscaled) in nodes.iter_mut() {
+        match unscaled {
+            Some(unscaled) if resized => {
+                scale_style(&mut style, &unscaled.style, scale);
+                if let Some(mut text) = text {
+                    scale_text(&mut text, &unscaled.fonts, scale);
+                }
+            }
+            Some(_) => {}
+            None => {
+                let unscaled = Unscaled {
+                    style: style.clone(),
+                    fonts: text
+                        .as_ref()
+                        .map(|text| text.sections.iter().map(|n| n.style.font_size).collect())
+                        .unwrap_or_default(),
+                };
+                scale_style(&mut style, &unscaled.style, scale);
+                if let Some(mut text) = text {
+                    scale_text(&mut text, &unscaled.fonts, scale);
+                }
+                commands.entity(entity).insert(unscaled);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ui_scale() {
+        assert_eq!(ui_scale(DESIGN_SIZE.x, DESIGN_SIZE.y), 1.0);
+        // a wide window is limited by its height
+        assert_eq!(ui_scale(3000.0, DESIGN_SIZE.y * 2.0), 2.0);
+        assert_eq!(ui_scale(0.0, 0.0), MIN_UI_SCALE);
+        let mut style = Style::default();
+        let unscaled = Style {
+            size: Size::new(Val::Px(100.0), Val::Percent(50.0)),
+            margin: UiRect::all(Val::Px(10.0)),
+            ..default()
+        };
+        scale_style(&mut style, &unscaled, 0.5);
+        assert_eq!(style.size, Size::new(Val::Px(50.0), Val::Percent(50.0)));
+        assert_eq!(style.margin, UiRect::all(Val::Px(5.0)));
+    }
+}
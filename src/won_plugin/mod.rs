@@ -1,4 +1,4 @@
-use crate::{game_plugin::Score, GameState};
+use crate::{actions_plugin::Navigable, game_plugin::Score, GameState};
 use bevy::prelude::*;
 
 pub struct WonPlugin;
@@ -14,16 +14,12 @@ impl Plugin for WonPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(SystemSet::on_enter(GameState::Won).with_system(setup))
             .add_system_set(SystemSet::on_exit(GameState::Won).with_system(exit))
-            .add_system_set(
-                SystemSet::on_update(GameState::Won)
-                    .with_system(keyboard_input_system)
-                    .with_system(button_system),
-            );
+            .add_system_set(SystemSet::on_update(GameState::Won).with_system(button_system));
     }
 }
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>, score: Res<Score>) {
-    let won_text = format!("{} Points", score.coins);
+    let won_text = format!("{} Points", score.points);
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -74,6 +70,9 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>, score: Res<Scor
                                     color: Color::rgb(0.9, 0.9, 0.9),
                                 },
                             ));
+                        })
+                        .insert(Navigable {
+                            color: NORMAL_BUTTON,
                         });
                     parent.spawn_bundle(TextBundle::from_section(
                         won_text,
@@ -103,15 +102,6 @@ fn exit(mut commands: Commands, destroy_query: Query<Entity, With<LocalEntity>>)
     }
 }
 
-fn keyboard_input_system(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut app_state: ResMut<State<GameState>>,
-) {
-    if keyboard_input.pressed(KeyCode::Return) {
-        app_state.set(GameState::Game).unwrap();
-    }
-}
-
 #[allow(clippy::complexity)]
 fn button_system(
     mut interaction_query: Query<